@@ -2,7 +2,9 @@
 
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::ArgValueCandidates;
+use multimoon::context::OutputFormat;
 
 /// Command line arguments.
 #[derive(Parser, Debug)]
@@ -18,7 +20,7 @@ pub struct Args {
     #[command(subcommand)]
     pub command: Command,
 
-    /// Specify MultiMoon registry URL. (default to MultiMoon official registry by Lone Outpost Tech)
+    /// Specify MultiMoon registry URL or directory. (default to MultiMoon official registry by Lone Outpost Tech)
     #[arg(long)]
     pub registry: Option<String>,
 
@@ -30,9 +32,37 @@ pub struct Args {
     #[arg(long)]
     pub multimoonhome: Option<PathBuf>,
 
-    /// Verbose output.
-    #[arg(short, long)]
-    pub verbose: bool,
+    /// Verbose output, repeat for more details. (`-vvv` includes messages of libraries)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only print warnings and errors.
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Don't add MoonBit to PATH in shell config files (or user environment variables on Windows).
+    #[arg(long, global = true)]
+    pub no_modify_path: bool,
+
+    /// Only show what would be changed (downloads, written and removed files, shell config edits and commands run),
+    /// without changing anything.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Don't ask for confirmation before overwriting or deleting modified files, e.g. a modified core library.
+    /// (confirmation is refused when not running in a terminal)
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Number of automatic backups to keep, taken before a modified core library is overwritten by a toolchain
+    /// install or a core restore. `0` disables automatic backups and asks for confirmation instead.
+    #[arg(long, global = true, value_name = "COUNT", default_value_t = multimoon::context::DEFAULT_AUTO_BACKUPS)]
+    pub auto_backups: usize,
+
+    /// Output format of query commands (`toolchain show`, `toolchain list`, `core list` and `core show`) and plans of
+    /// `--dry-run`. Progress messages are printed to stderr in `json` format.
+    #[arg(long, value_enum, global = true, default_value_t = Format::Text)]
+    pub format: Format,
 }
 
 /// Top level subcommand.
@@ -44,14 +74,124 @@ pub enum Command {
     /// Update MoonBit toolchains to latest version.
     Update,
 
+    /// Print environment variables for using MoonBit, e.g. `eval "$(multimoon env)"`.
+    Env(EnvArgs),
+
+    /// Print shell completion script, e.g. `source <(multimoon completions bash)`.
+    Completions(CompletionsArgs),
+
     /// Manipulate MoonBit toolchains. (list, update or revert)
     Toolchain(ToolchainArgs),
 
-    /// Manipulate the `core` library. (list, update, revert, backup or restore)
+    /// Manipulate the `core` library. (list, show, update, revert, backup or restore)
     Core(CoreArgs),
 
-    /// Show a help for how to update MultiMoon itself. (Actual self update is not yet implemented)
-    UpdateSelf,
+    /// Manipulate optional components of current toolchain. (list, add or remove)
+    Component(ComponentArgs),
+
+    /// List all installers supported by this version of MultiMoon.
+    Installers,
+
+    /// Manipulate MultiMoon registries. (mirror, build or validate)
+    Registry(RegistryArgs),
+
+    /// Uninstall MoonBit toolchain and remove all data of MultiMoon.
+    Uninstall(UninstallArgs),
+
+    /// Update MultiMoon itself to the latest release in the registry. (checked by checksum, not signature)
+    UpdateSelf(UpdateSelfArgs),
+
+    /// Install or uninstall MultiMoon itself.
+    #[command(name = "self")]
+    SelfManage(SelfArgs),
+}
+
+/// Argument for `env`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct EnvArgs {
+    /// Output format. (default to current shell)
+    #[arg(long, value_enum)]
+    pub shell: Option<EnvShell>,
+
+    /// Append to `$GITHUB_PATH` and `$GITHUB_ENV` files for later steps of a GitHub Actions job, instead of printing.
+    #[arg(long, conflicts_with = "shell")]
+    pub github: bool,
+}
+
+/// Output format of `env`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvShell {
+    Sh,
+    Bash,
+    Zsh,
+    Fish,
+    Nu,
+    Powershell,
+    Tcsh,
+    Cmd,
+    Json,
+}
+
+/// Output format of query commands and plans.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Text => OutputFormat::Text,
+            Format::Json => OutputFormat::Json,
+        }
+    }
+}
+
+/// Argument for `completions`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct CompletionsArgs {
+    /// Shell to generate completion script for.
+    #[arg(value_parser = clap::value_parser!(clap_complete::Shell))]
+    pub shell: clap_complete::Shell,
+}
+
+/// Argument for `update-self`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct UpdateSelfArgs {
+    /// Only check if a new version is available, without updating.
+    #[arg(long)]
+    pub check: bool,
+}
+
+/// Argument for `self`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct SelfArgs {
+    #[command(subcommand)]
+    pub command: SelfCommand,
+}
+
+/// Subcommand of `self`.
+#[derive(Subcommand, Debug)]
+pub enum SelfCommand {
+    /// Copy this executable into the `bin` directory of MultiMoon storage dir, and add it to PATH.
+    Install(SelfInstallArgs),
+
+    /// Remove the installed executable of MultiMoon and its PATH entry, keeping MoonBit and other data.
+    Uninstall,
+}
+
+/// Argument for `self install`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct SelfInstallArgs {
+    /// Also install the latest MoonBit toolchain.
+    #[arg(long)]
+    pub with_toolchain: bool,
 }
 
 /// Argument for `toolchain`.
@@ -85,7 +225,7 @@ pub enum ToolchainCommand {
 #[command()]
 pub struct ToolchainUpdateArgs {
     /// Specified toolchain version.
-    #[arg()]
+    #[arg(add = ArgValueCandidates::new(crate::subcommand::completions::toolchain_candidates))]
     pub toolchain: String,
 
     /// Force reinstall even if specified toolchain is currently installed.
@@ -107,7 +247,10 @@ pub struct CoreArgs {
 #[derive(Subcommand, Debug)]
 pub enum CoreCommand {
     /// List all backups.
-    List,
+    List(CoreListArgs),
+
+    /// Show details of a backup.
+    Show(CoreShowArgs),
 
     /// Backup current MoonBit core library.
     Backup(CoreBackupArgs),
@@ -116,12 +259,34 @@ pub enum CoreCommand {
     Restore(CoreRestoreArgs),
 }
 
+/// Argument for `core list`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct CoreListArgs {
+    /// Also show creation time, toolchain, size and description of backups.
+    #[arg(short, long)]
+    pub long: bool,
+}
+
+/// Argument for `core show`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct CoreShowArgs {
+    /// Backup name.
+    #[arg(add = ArgValueCandidates::new(crate::subcommand::completions::core_backup_candidates))]
+    pub name: String,
+}
+
 /// Argument for `core backup`.
 #[derive(Parser, Debug)]
 #[command()]
 pub struct CoreBackupArgs {
     /// Backup name. (defaults to current date and time)
     pub name: Option<String>,
+
+    /// Description of the backup, shown by `core list --long` and `core show`.
+    #[arg(short = 'm', long)]
+    pub description: Option<String>,
 }
 
 /// Argument for `core restore`.
@@ -129,9 +294,132 @@ pub struct CoreBackupArgs {
 #[command()]
 pub struct CoreRestoreArgs {
     /// Restore name.
+    #[arg(add = ArgValueCandidates::new(crate::subcommand::completions::core_backup_candidates))]
     pub name: String,
 }
 
+/// Argument for `component`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct ComponentArgs {
+    /// Subcommands.
+    #[command(subcommand)]
+    pub command: ComponentCommand,
+}
+
+/// Second level subcommand for `component`.
+#[derive(Subcommand, Debug)]
+pub enum ComponentCommand {
+    /// List all components of current toolchain.
+    List,
+
+    /// Add components to current toolchain.
+    Add(ComponentNamesArgs),
+
+    /// Remove optional components from current toolchain.
+    Remove(ComponentNamesArgs),
+}
+
+/// Argument for `component add` and `component remove`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct ComponentNamesArgs {
+    /// Component names.
+    #[arg(required = true)]
+    pub names: Vec<String>,
+}
+
+/// Argument for `uninstall`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct UninstallArgs {
+    /// Keep backups of core library.
+    #[arg(long)]
+    pub keep_backups: bool,
+}
+
+/// Argument for `registry`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct RegistryArgs {
+    /// Subcommands.
+    #[command(subcommand)]
+    pub command: RegistryCommand,
+}
+
+/// Second level subcommand for `registry`.
+#[derive(Subcommand, Debug)]
+pub enum RegistryCommand {
+    /// Replicate the registry (specified by `--registry`) to a local directory.
+    Mirror(RegistryMirrorArgs),
+
+    /// Build registry indexes from toolchain files in a local directory.
+    Build(RegistryBuildArgs),
+
+    /// Check a registry index for errors, and print a report in JSON.
+    Validate(RegistryValidateArgs),
+}
+
+/// Argument for `registry mirror`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct RegistryMirrorArgs {
+    /// Output directory of the mirrored registry.
+    pub output: PathBuf,
+
+    /// Platforms to mirror, can be specified multiple times. (default to current platform)
+    #[arg(long = "arch", value_parser = clap::builder::PossibleValuesParser::new(crate::global::ARCHS))]
+    pub archs: Vec<String>,
+
+    /// Mirror all platforms.
+    #[arg(long, conflicts_with = "archs")]
+    pub all_archs: bool,
+
+    /// Toolchains to mirror, can be specified multiple times. (default to all toolchains)
+    #[arg(long = "toolchain")]
+    pub toolchains: Vec<String>,
+
+    /// `downloadfrom` written to the mirrored index, relative to the index file or absolute. (default to the mirror root)
+    #[arg(long, default_value = "../")]
+    pub downloadfrom: String,
+}
+
+/// Argument for `registry build`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct RegistryBuildArgs {
+    /// Registry directory, laid out as `<toolchain>/<arch>/<binary>.xz` and `<toolchain>/multiarch/<core>.zip`.
+    pub path: PathBuf,
+
+    /// Installer of the toolchains.
+    #[arg(long, default_value = "initial")]
+    pub installer: String,
+
+    /// JSON file of installation steps for the `declarative` installer.
+    #[arg(long)]
+    pub install_spec: Option<PathBuf>,
+
+    /// `downloadfrom` written to the indexes, relative to the index file or absolute. (default to the registry root)
+    #[arg(long, default_value = "../")]
+    pub downloadfrom: String,
+}
+
+/// Argument for `registry validate`.
+#[derive(Parser, Debug)]
+#[command()]
+pub struct RegistryValidateArgs {
+    /// Registry index to validate, an URL, a file path or a directory containing `index.json`.
+    pub index: String,
+
+    /// Platform of the index. (default to the name of the directory containing the index, or current platform)
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(crate::global::ARCHS))]
+    pub arch: Option<String>,
+
+    /// Also download all files and verify their checksums.
+    #[arg(long)]
+    pub download: bool,
+}
+
 
 const CLAP_HELP_TEMPLATE: &'static str = "{before-help}{about-with-newline}
 Presented by {author-with-newline}
@@ -1,6 +1,14 @@
 //! Common utilities.
 
-// use crate::prelude::*;
+use anyhow::Context as _;
+
+use crate::prelude::*;
+
+/// Compute checksum of `data` in the format of registry files (`sha256:<hex>`).
+pub fn sha256_checksum(data: &[u8]) -> String {
+    use sha2::{Sha256, Digest};
+    format!("sha256:{}", base16ct::lower::encode_string(&Sha256::digest(data)))
+}
 
 pub fn timestamp_from_zipfile(file: zip::read::ZipFile, fallback: i64) -> i64 {
     // TODO: remove `chrome` crate, use `time` crate for local datetime instead
@@ -17,3 +25,137 @@ pub fn timestamp_from_zipfile(file: zip::read::ZipFile, fallback: i64) -> i64 {
         fallback
     }
 }
+
+/// Compare two dotted version strings (e.g. `0.1.2`) numerically. Any pre-release or build suffix
+/// (after `-` or `+`) and a leading `v` are ignored, missing components are treated as zero.
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    fn components(version: &str) -> Vec<u64> {
+        let version = version.trim().trim_start_matches('v');
+        let version = version.split(['-', '+']).next().unwrap_or_default();
+        version.split('.').map(|c| c.parse::<u64>().unwrap_or(0)).collect()
+    }
+    let (a, b) = (components(a), components(b));
+    for i in 0..(a.len().max(b.len())) {
+        let ordering = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+    std::cmp::Ordering::Equal
+}
+
+/// Decompress xz compressed data, which is the format of registry binaries.
+pub fn xz_decompress(compressed: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut xzdecoder = xz2::bufread::XzDecoder::new(compressed);
+    let mut content = vec![];
+    xzdecoder.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Write `content` to `path` by writing a temporary file in the same directory then renaming it,
+/// so readers never see a partially written file.
+pub fn write_file_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let file_name = path.file_name()
+        .with_context(|| format!("invalid file path {}", path.display()))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    std::fs::write(&tmp_path, content)
+        .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Replace (or create) `executable` with `content`, by writing it next to the executable then renaming it over.
+pub fn replace_executable(executable: &Path, content: &[u8]) -> Result<()> {
+    let with_suffix = |suffix: &str| {
+        let mut name = executable.file_name().unwrap_or_default().to_os_string();
+        name.push(suffix);
+        executable.with_file_name(name)
+    };
+
+    let new_path = with_suffix(".new");
+    std::fs::write(&new_path, content)
+        .with_context(|| format!("failed to write {}", new_path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new_path, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("failed to set permissions of {}", new_path.display()))?;
+
+        // renaming is atomic, and the running process keeps the replaced file
+        std::fs::rename(&new_path, executable)
+            .with_context(|| format!("failed to replace {}", executable.display()))?;
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    {
+        if !executable.exists() {
+            std::fs::rename(&new_path, executable)
+                .with_context(|| format!("failed to write {}", executable.display()))?;
+            return Ok(());
+        }
+
+        // a running executable cannot be overwritten on Windows, but it can be renamed away
+        let old_path = with_suffix(".old");
+        let _ = std::fs::remove_file(&old_path);
+        std::fs::rename(executable, &old_path)
+            .with_context(|| format!("failed to move {}", executable.display()))?;
+        if let Err(err) = std::fs::rename(&new_path, executable) {
+            let _ = std::fs::rename(&old_path, executable);
+            return Err(Into::<anyhow::Error>::into(err)
+                .context(format!("failed to replace {}", executable.display())));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(windows, unix)))]
+    {
+        compile_error!("unsupported platform")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering::*;
+
+    use super::*;
+
+    #[test]
+    fn compare_numerically() {
+        assert_eq!(compare_versions("0.1.10", "0.1.9"), Greater);
+        assert_eq!(compare_versions("0.2.0", "0.10.0"), Less);
+        assert_eq!(compare_versions("1.0.0", "1.0.0"), Equal);
+    }
+
+    #[test]
+    fn compare_ignores_prefix_and_whitespace() {
+        assert_eq!(compare_versions("v0.1.2", "0.1.2"), Equal);
+        assert_eq!(compare_versions(" v0.1.3\n", "v0.1.2"), Greater);
+    }
+
+    #[test]
+    fn compare_missing_components_as_zero() {
+        assert_eq!(compare_versions("0.1", "0.1.0"), Equal);
+        assert_eq!(compare_versions("0.1", "0.1.1"), Less);
+        assert_eq!(compare_versions("1", "0.9.9"), Greater);
+    }
+
+    #[test]
+    fn compare_ignores_suffixes() {
+        assert_eq!(compare_versions("0.1.2-rc.1", "0.1.2"), Equal);
+        assert_eq!(compare_versions("0.1.2+20240101", "0.1.2"), Equal);
+        assert_eq!(compare_versions("0.1.3-rc.1", "0.1.2"), Greater);
+    }
+
+    #[test]
+    fn compare_invalid_components_as_zero() {
+        assert_eq!(compare_versions("0.x.1", "0.0.1"), Equal);
+        assert_eq!(compare_versions("", "0.0.0"), Equal);
+    }
+}
@@ -0,0 +1,58 @@
+//! Optional components of toolchains.
+
+use anyhow::Context as _;
+use serde::{Serialize, Deserialize};
+
+use crate::{error::Error, prelude::*};
+use crate::registry::{File, Toolchain};
+
+/// File name of the component state, stored in MoonBit home directory.
+pub const STATE_FILENAME: &str = ".multimoon-components.json";
+
+/// Components removed from the MoonBit installation by user.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ComponentState {
+    #[serde(default)]
+    pub removed: Vec<String>,
+}
+
+/// Path of the component state file.
+pub fn state_path(ctx: &Context) -> PathBuf {
+    ctx.moonhome.join(STATE_FILENAME)
+}
+
+pub fn load(ctx: &Context) -> Result<ComponentState> {
+    let path = state_path(ctx);
+    match std::fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content)
+            .with_context(|| Error::Filesystem(format!("invalid component state {}", path.display()))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(ComponentState::default()),
+        Err(err) => Err(Into::<anyhow::Error>::into(err)
+            .context(format!("error reading component state {}", path.display()))),
+    }
+}
+
+pub fn save(ctx: &Context, state: &ComponentState) -> Result<()> {
+    std::fs::create_dir_all(&ctx.moonhome)?;
+    let path = state_path(ctx);
+    crate::common::write_file_atomic(&path, &serde_json::to_vec_pretty(state)?)
+}
+
+/// Check if component `name` of `toolchain` can be removed.
+pub fn is_optional(toolchain: &Toolchain, name: &str) -> bool {
+    toolchain.components.iter().any(|c| c.name == name && c.optional)
+}
+
+/// Check if `file` should be installed. Files of required components are always installed, even if removed before.
+pub fn is_selected(state: &ComponentState, toolchain: &Toolchain, file: &File) -> bool {
+    match &file.component {
+        Some(name) => !(is_optional(toolchain, name) && state.removed.contains(name)),
+        None => true,
+    }
+}
+
+/// Split binaries of `toolchain` into (selected, removed) according to current component state.
+pub fn split_binaries(ctx: &Context, toolchain: &Toolchain) -> Result<(Vec<File>, Vec<File>)> {
+    let state = load(ctx)?;
+    Ok(toolchain.bin.iter().cloned().partition(|f| is_selected(&state, toolchain, f)))
+}
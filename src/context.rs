@@ -0,0 +1,65 @@
+//! Context of MultiMoon operations.
+
+use anyhow::Context as _;
+
+use crate::prelude::*;
+
+/// The official registry by Lone Outpost Tech.
+pub const DEFAULT_REGISTRY: &str = "https://multimoon.lopt.dev/";
+
+/// Number of automatic core backups kept by default.
+pub const DEFAULT_AUTO_BACKUPS: usize = 5;
+
+/// Output format of query commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Locations and settings MultiMoon operates with, passed explicitly to all operations. Contexts are independent of
+/// each other, so several MoonBit homes can be managed in the same process.
+#[derive(Clone, Debug)]
+pub struct Context {
+    /// Home directory of the user, where shell config files are.
+    pub home: PathBuf,
+    /// Data storage location of MultiMoon.
+    pub multimoonhome: PathBuf,
+    /// Installation path of MoonBit.
+    pub moonhome: PathBuf,
+    /// Registry URL (with a trailing slash).
+    pub registry: Url,
+    /// Add MoonBit to PATH of the user when installing.
+    pub modify_path: bool,
+    /// Output format of query commands.
+    pub format: OutputFormat,
+    /// Only compute what would be changed, instead of changing anything.
+    pub dry_run: bool,
+    /// Overwrite or delete modified files without asking for confirmation.
+    pub yes: bool,
+    /// Number of automatic backups of a modified core library to keep. `0` disables automatic backups.
+    pub auto_backups: usize,
+}
+
+impl Context {
+    /// Context with default locations in the home directory of current user.
+    pub fn new() -> Result<Self> {
+        let home = dirs::home_dir().context("unable to detect user's home directory")?;
+        Ok(Self::with_home(home))
+    }
+
+    /// Context with default locations in `home`, i.e. `.moon` and `.multimoon`, and the official registry.
+    pub fn with_home(home: PathBuf) -> Self {
+        Context {
+            multimoonhome: home.join(".multimoon"),
+            moonhome: home.join(".moon"),
+            home,
+            registry: Url::parse(DEFAULT_REGISTRY).unwrap(),
+            modify_path: true,
+            format: OutputFormat::Text,
+            dry_run: false,
+            yes: false,
+            auto_backups: DEFAULT_AUTO_BACKUPS,
+        }
+    }
+}
@@ -2,9 +2,10 @@
 
 use walkdir::WalkDir;
 use std::{io::{Cursor, Read, Seek, Write}, time::UNIX_EPOCH};
-use anyhow::Context;
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
 
-use crate::prelude::*;
+use crate::{error::Error, installer::remove_dir_if_exists, report::{Event, Reporter}, prelude::*};
 
 #[derive(Clone, Debug)]
 pub struct ExtractOptions {
@@ -17,19 +18,19 @@ impl Default for ExtractOptions {
     }
 }
 
-pub async fn archive<P: AsRef<Path>>(lib_path: P) -> Result<zip::ZipArchive<Cursor<Vec<u8>>>> {
+pub async fn archive<P: AsRef<Path>>(lib_path: P, reporter: &dyn Reporter) -> Result<zip::ZipArchive<Cursor<Vec<u8>>>> {
     let lib_path = lib_path.as_ref();
     let lib_core_path = lib_path.join("core");
 
-    println!("archiving {}", lib_core_path.display());
+    reporter.report(&Event::ArchiveStarted { path: &lib_core_path });
 
     // check is core
     if !(lib_core_path.exists()) {
-        return Err(anyhow!("archive error: core path {} doesn't exist", lib_core_path.display()));
+        return Err(Error::NotFound(format!("archive error: core path {} doesn't exist", lib_core_path.display())).into());
     }
     let moon_mod_json_path = lib_core_path.join("moon.mod.json");
     if !(moon_mod_json_path.exists()) {
-        return Err(anyhow!("archive error: core json {} is missing", moon_mod_json_path.display()));
+        return Err(Error::NotFound(format!("archive error: core json {} is missing", moon_mod_json_path.display())).into());
     }
 
     // create archive
@@ -73,11 +74,7 @@ pub async fn archive<P: AsRef<Path>>(lib_path: P) -> Result<zip::ZipArchive<Curs
         let (add_file, add_dir) = (path_abs.is_file(), (path_abs.is_dir() && (!path_rel.as_os_str().is_empty())));
 
         if add_file || add_dir {
-            if global().verbose || archived_count < 5 {
-                println!("archiving {}", path_rel.display());
-            } else if (!global().verbose) && archived_count == 5 {
-                println!(" (further archived files omitted)");
-            }
+            reporter.report(&Event::FileArchived { count: archived_count, path: path_rel });
             archived_count += 1;
         }
 
@@ -96,7 +93,7 @@ pub async fn archive<P: AsRef<Path>>(lib_path: P) -> Result<zip::ZipArchive<Curs
     Ok(zip_buf)
 }
 
-pub async fn extract_verbose<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>, options: &ExtractOptions) -> Result<()>
+pub async fn extract_verbose<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>, options: &ExtractOptions, reporter: &dyn Reporter) -> Result<()>
     where P: AsRef<Path>, R: Read + Seek
 {
     use crate::common::timestamp_from_zipfile;
@@ -104,6 +101,7 @@ pub async fn extract_verbose<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>
     let lib_path = lib_path.as_ref();
     let lib_core_path = lib_path.join("core");
 
+    reporter.report(&Event::ExtractStarted { lib_path });
     let mut extracted_file_count = 0;
     for i in 0..(archive.len()) {
         let mut file = archive.by_index(i)
@@ -113,8 +111,8 @@ pub async fn extract_verbose<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>
             None => continue,
         };
         if !outpath.starts_with(&lib_core_path) {
-            return Err(anyhow!("extract error: extracted path {} is not within core path {}! (invalid core archive?) {}", 
-                outpath.display(), lib_core_path.display(), CORRUPT));
+            return Err(Error::Extract(format!("extract error: extracted path {} is not within core path {}! (invalid core archive?) {}", 
+                outpath.display(), lib_core_path.display(), CORRUPT)).into());
         }
 
         if file.is_dir() {
@@ -127,11 +125,7 @@ pub async fn extract_verbose<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>
                         .with_context(|| format!("extract error: failed to create {} {}", outpath.display(), CORRUPT))?
                 }
             }
-            if global().verbose || extracted_file_count < 5 {
-                println!("extract to {}", outpath.display());
-            } else if (!global().verbose) && extracted_file_count == 5 {
-                println!(" (further extracted files omitted)");
-            }
+            reporter.report(&Event::FileExtracted { count: extracted_file_count, path: &outpath });
             let mut outfile = std::fs::File::create(&outpath)
                 .with_context(|| format!("extract error: failed to create {} {}", outpath.display(), CORRUPT))?;
             std::io::copy(&mut file, &mut outfile)
@@ -181,10 +175,95 @@ pub async fn extract_verbose<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>
         }
     }
 
+    reporter.report(&Event::ExtractFinished { lib_path, files: extracted_file_count });
     Ok(())
 }
 
-pub async fn list() -> Result<Vec<String>> {
+/// A backup of core library in MultiMoon storage dir.
+#[derive(Serialize, Clone, Debug)]
+pub struct Backup {
+    pub name: String,
+    /// Modification time of the backup file in unix seconds.
+    pub last_modified: i64,
+    /// Metadata, `None` for backups created by older versions of MultiMoon.
+    pub metadata: Option<BackupMetadata>,
+}
+
+/// Metadata of a core backup, stored next to the backup archive as `<name>.json`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BackupMetadata {
+    /// Toolchain active when the backup was created, if it was found in the registry.
+    pub toolchain: Option<String>,
+    /// MoonBit version of that toolchain.
+    pub moonver: Option<String>,
+    /// Description given by the user.
+    pub description: Option<String>,
+    /// Number of files in the backup.
+    pub files: usize,
+    /// Total size of the files in bytes, uncompressed.
+    pub size: u64,
+    /// Digest of the content, see [`digest`].
+    pub digest: String,
+    /// Checked out commit, if the core is a git checkout.
+    pub git_commit: Option<String>,
+}
+
+/// Metadata that can be read from a core archive: file count, size and digest, all of the files covered by
+/// [`archive_digest`].
+pub fn archive_metadata<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Result<BackupMetadata> {
+    let (mut files, mut size) = (0, 0);
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if file.is_file() && file.enclosed_name().is_some_and(|path| is_core_file(&path)) {
+            files += 1;
+            size += file.size();
+        }
+    }
+    Ok(BackupMetadata { files, size, digest: archive_digest(archive)?, ..BackupMetadata::default() })
+}
+
+/// Commit checked out in `lib_path/core`, if it's a git checkout and git is available.
+pub fn git_commit<P: AsRef<Path>>(lib_path: P) -> Option<String> {
+    let lib_core_path = lib_path.as_ref().join("core");
+    if !lib_core_path.join(".git").exists() {
+        return None;
+    }
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(&lib_core_path)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Ok(output) => {
+            debug!("unable to get git commit of {}: {}", lib_core_path.display(), String::from_utf8_lossy(&output.stderr).trim());
+            None
+        },
+        Err(err) => {
+            debug!("unable to get git commit of {}: {}", lib_core_path.display(), err);
+            None
+        },
+    }
+}
+
+/// Metadata of backup `name`, `None` if it was not recorded.
+pub fn load_metadata(ctx: &Context, name: &str) -> Result<Option<BackupMetadata>> {
+    let path = metadata_path(ctx, name);
+    let content = match std::fs::read(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let metadata = serde_json::from_slice(&content)
+        .with_context(|| Error::Filesystem(format!("invalid backup metadata {}", path.display())))?;
+    Ok(Some(metadata))
+}
+
+fn metadata_path(ctx: &Context, name: &str) -> PathBuf {
+    core_backups_path(ctx).join(format!("{}.json", name))
+}
+
+/// All core backups, oldest first.
+pub async fn list(ctx: &Context) -> Result<Vec<Backup>> {
     fn process_direntry(entry: Result<std::fs::DirEntry, std::io::Error>) -> Result<Option<(String, i64)>> {
         let entry = entry?;
         let file_type = entry.file_type()?;
@@ -197,7 +276,7 @@ pub async fn list() -> Result<Vec<String>> {
                     .and_then(|systime| systime.duration_since(UNIX_EPOCH).ok())
                     .map(|duration| duration.as_secs() as i64)
                     .unwrap_or(0);
-                let disp_name = format!("{}", backup_name);
+                let disp_name = backup_name.to_string();
                 return Ok(Some((disp_name, timestamp)));
             }
         }
@@ -205,7 +284,7 @@ pub async fn list() -> Result<Vec<String>> {
     }
 
     let mut result = vec![];
-    let readdir = std::fs::read_dir(core_backups_path())?;
+    let readdir = std::fs::read_dir(core_backups_path(ctx))?;
     for entry in readdir {
         match process_direntry(entry) {
             Ok(Some(backup_name)) => {
@@ -213,22 +292,418 @@ pub async fn list() -> Result<Vec<String>> {
             },
             Ok(None) => (),
             Err(err) => {
-                println!("ignoring a file due to error: {}", err)
+                warn!("ignoring a file due to error: {}", err)
             },
         }
     }
     result.sort_by(|a, b| a.1.cmp(&b.1));
     
-    Ok(result.into_iter().map(|(filename, _)| filename).collect())
+    Ok(result.into_iter().map(|(name, last_modified)| {
+        let metadata = load_metadata(ctx, &name).unwrap_or_else(|err| {
+            warn!("ignoring metadata of backup {} due to error: {:#}", &name, err);
+            None
+        });
+        Backup { name, last_modified, metadata }
+    }).collect())
+}
+
+/// Digest of core library files in `lib_path/core` (ignoring build output in `target`), comparable with
+/// [`archive_digest`]. `None` if there is no core.
+pub fn digest<P: AsRef<Path>>(lib_path: P) -> Result<Option<String>> {
+    let lib_path = lib_path.as_ref();
+    let lib_core_path = lib_path.join("core");
+    if !lib_core_path.is_dir() {
+        return Ok(None);
+    }
+
+    let mut files = vec![];
+    for entry in WalkDir::new(&lib_core_path) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let path_rel = entry.path().strip_prefix(lib_path)?;
+            if is_core_file(path_rel) {
+                files.push((digest_name(path_rel), entry.path().to_path_buf()));
+            }
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut hasher = CoreHasher::new();
+    for (name, path) in files {
+        let content = std::fs::read(&path)
+            .with_context(|| format!("error reading file {}", path.display()))?;
+        hasher.update(&name, &content);
+    }
+    Ok(Some(hasher.finish()))
+}
+
+/// Digest of core library files in a core archive, comparable with [`digest`].
+pub fn archive_digest<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Result<String> {
+    let mut files = vec![];
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if let Some(path) = file.enclosed_name() {
+            if file.is_file() && is_core_file(&path) {
+                files.push((digest_name(&path), i));
+            }
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut hasher = CoreHasher::new();
+    for (name, i) in files {
+        let mut content = vec![];
+        archive.by_index(i)?.read_to_end(&mut content)?;
+        hasher.update(&name, &content);
+    }
+    Ok(hasher.finish())
+}
+
+/// Name of the first core backup with the same content as digest `digest`.
+pub fn find_backup(ctx: &Context, digest: &str) -> Result<Option<String>> {
+    let readdir = match std::fs::read_dir(core_backups_path(ctx)) {
+        Ok(readdir) => readdir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in readdir {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".zip")) else {
+            continue;
+        };
+        // digest in metadata saves reading the archive
+        if let Ok(Some(metadata)) = load_metadata(ctx, name) {
+            if metadata.digest == digest {
+                return Ok(Some(name.to_string()));
+            }
+            continue;
+        }
+        let result = std::fs::File::open(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(zip::ZipArchive::new(file)?))
+            .and_then(|mut archive| archive_digest(&mut archive));
+        match result {
+            Ok(backup_digest) if backup_digest == digest => return Ok(Some(name.to_string())),
+            Ok(_) => (),
+            Err(err) => warn!("ignoring backup {} due to error: {}", path.display(), err),
+        }
+    }
+    Ok(None)
+}
+
+/// Files of a core archive or directory, relative to lib path, excluding build output.
+fn is_core_file(path_rel: &Path) -> bool {
+    path_rel.starts_with("core") && !path_rel.starts_with(Path::new("core").join("target"))
+}
+
+/// Platform independent name of a core file in digests.
+fn digest_name(path_rel: &Path) -> String {
+    path_rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Hashes names and contents of core files, in a sorted order.
+struct CoreHasher(sha2::Sha256);
+
+impl CoreHasher {
+    fn new() -> Self {
+        use sha2::Digest;
+        CoreHasher(sha2::Sha256::new())
+    }
+
+    fn update(&mut self, name: &str, content: &[u8]) {
+        use sha2::Digest;
+        self.0.update(name.as_bytes());
+        self.0.update([0]);
+        self.0.update((content.len() as u64).to_le_bytes());
+        self.0.update(content);
+    }
+
+    fn finish(self) -> String {
+        use sha2::Digest;
+        format!("sha256:{}", base16ct::lower::encode_string(&self.0.finalize()))
+    }
 }
 
 #[inline(always)]
-pub async fn extract<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>) -> Result<()>
+pub async fn extract<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>, reporter: &dyn Reporter) -> Result<()>
     where P: AsRef<Path>, R: Read + Seek
 {
-    extract_verbose(lib_path, archive, &(ExtractOptions::default())).await
+    extract_verbose(lib_path, archive, &(ExtractOptions::default()), reporter).await
+}
+
+/// Replace `lib_path/core` with exactly the content of `archive`. The archive is extracted to a staging directory in
+/// `lib_path` first, then swapped with current core, so a failed extraction leaves current core untouched.
+pub async fn replace<P, R>(lib_path: P, archive: &mut zip::ZipArchive<R>, reporter: &dyn Reporter) -> Result<()>
+    where P: AsRef<Path>, R: Read + Seek
+{
+    let lib_path = lib_path.as_ref();
+    let lib_core_path = lib_path.join("core");
+    let staging_path = lib_path.join(".multimoon-staging");
+    let old_core_path = lib_path.join(".multimoon-old-core");
+
+    // extract to `<staging>/core`
+    remove_dir_if_exists(&staging_path)?;
+    std::fs::create_dir_all(&staging_path)
+        .with_context(|| format!("extract error: failed to create {}", staging_path.display()))?;
+    if let Err(err) = extract(&staging_path, archive, reporter).await {
+        let _ = std::fs::remove_dir_all(&staging_path);
+        return Err(err);
+    }
+    let staging_core_path = staging_path.join("core");
+    if !staging_core_path.is_dir() {
+        let _ = std::fs::remove_dir_all(&staging_path);
+        return Err(Error::Extract("extract error: no core found in archive (invalid core archive?)".to_string()).into());
+    }
+
+    // swap directories, moving current core back on failure
+    remove_dir_if_exists(&old_core_path)?;
+    let has_core = lib_core_path.exists();
+    if has_core {
+        std::fs::rename(&lib_core_path, &old_core_path)
+            .with_context(|| format!("extract error: failed to move {} away", lib_core_path.display()))?;
+    }
+    if let Err(err) = std::fs::rename(&staging_core_path, &lib_core_path) {
+        if has_core {
+            let _ = std::fs::rename(&old_core_path, &lib_core_path);
+        }
+        return Err(Into::<anyhow::Error>::into(err)
+            .context(format!("extract error: failed to move extracted core to {}", lib_core_path.display())));
+    }
+
+    // cleaning up is best effort, leftovers are removed by the next replacement
+    let _ = std::fs::remove_dir_all(&old_core_path);
+    let _ = std::fs::remove_dir_all(&staging_path);
+    Ok(())
+}
+
+/// File name of the record of the installed core, stored in MoonBit home directory next to the component state.
+pub const INSTALLED_FILENAME: &str = ".multimoon-core.json";
+
+/// Core library as installed from the registry, to tell later if it was modified without downloading it again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstalledCore {
+    /// Toolchain the core was installed with.
+    pub toolchain: String,
+    /// MoonBit version of that toolchain.
+    pub moonver: String,
+    /// Digest of the installed core, see [`digest`].
+    pub digest: String,
+}
+
+impl InstalledCore {
+    /// Record of installing core `archive` of `toolchain`.
+    pub fn new<R: Read + Seek>(toolchain: &crate::registry::Toolchain, archive: &mut zip::ZipArchive<R>) -> Result<Self> {
+        Ok(InstalledCore {
+            toolchain: toolchain.name.clone(),
+            moonver: toolchain.moonver.clone(),
+            digest: archive_digest(archive)?,
+        })
+    }
+}
+
+/// Path of the record of the installed core.
+pub fn installed_path(ctx: &Context) -> PathBuf {
+    ctx.moonhome.join(INSTALLED_FILENAME)
+}
+
+/// Record of the installed core, `None` if it was installed by an older version of MultiMoon (or not at all).
+pub fn load_installed(ctx: &Context) -> Result<Option<InstalledCore>> {
+    let path = installed_path(ctx);
+    match std::fs::read(&path) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)
+            .with_context(|| Error::Filesystem(format!("invalid record of installed core {}", path.display())))?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Into::<anyhow::Error>::into(err)
+            .context(format!("error reading record of installed core {}", path.display()))),
+    }
+}
+
+pub fn save_installed(ctx: &Context, installed: &InstalledCore) -> Result<()> {
+    crate::common::write_file_atomic(&installed_path(ctx), &serde_json::to_vec_pretty(installed)?)
+}
+
+pub fn core_backups_path(ctx: &Context) -> PathBuf {
+    ctx.multimoonhome.join("core-backups")
+}
+
+/// Name prefix of backups taken automatically before a modified core library is overwritten.
+pub const AUTO_BACKUP_PREFIX: &str = "auto-before-update-";
+
+/// Name for a new automatic backup, with a counter suffix if a backup of the same time already exists.
+pub fn auto_backup_name(ctx: &Context) -> String {
+    let name = format!("{}{}", AUTO_BACKUP_PREFIX, chrono::Local::now().format("%Y%m%d-%H%M%S-%3f"));
+    let exists = |name: &str| core_backups_path(ctx).join(format!("{}.zip", name)).exists();
+    if !exists(&name) {
+        return name;
+    }
+    (1..).map(|i| format!("{}-{}", name, i)).find(|name| !exists(name)).unwrap()
 }
 
-pub fn core_backups_path() -> PathBuf {
-    global().multimoonhome.as_path().join("core-backups")
+/// Write `archive` (created by [`archive`]) as backup `name` with its `metadata`, failing if the backup already exists.
+pub fn save_backup(ctx: &Context, name: &str, archive: zip::ZipArchive<Cursor<Vec<u8>>>, metadata: &BackupMetadata) -> Result<PathBuf> {
+    let write_path = core_backups_path(ctx).join(format!("{}.zip", name));
+    std::fs::create_dir_all(core_backups_path(ctx))?;
+    let mut write_file = std::fs::File::create_new(&write_path)
+        .with_context(|| format!("failed to create backup file {}", write_path.display()))?;
+    write_file.write_all(archive.into_inner().get_ref())?;
+    crate::common::write_file_atomic(&metadata_path(ctx, name), &serde_json::to_vec_pretty(metadata)?)?;
+    Ok(write_path)
+}
+
+/// Files of backup `name`, i.e. the archive and its metadata (if any).
+pub fn backup_files(ctx: &Context, name: &str) -> Vec<PathBuf> {
+    crate::plan::existing([core_backups_path(ctx).join(format!("{}.zip", name)), metadata_path(ctx, name)])
+}
+
+/// Files of automatic backups beyond the `keep` most recent ones, oldest first.
+pub async fn expired_auto_backups(ctx: &Context, keep: usize) -> Result<Vec<PathBuf>> {
+    if !core_backups_path(ctx).exists() {
+        return Ok(vec![]);
+    }
+    // names tell the time of automatic backups more precisely than modification times
+    let mut auto_backups = list(ctx).await?
+        .into_iter()
+        .filter(|backup| backup.name.starts_with(AUTO_BACKUP_PREFIX))
+        .collect::<Vec<_>>();
+    auto_backups.sort_by(|a, b| a.name.cmp(&b.name));
+    let expired = auto_backups.len().saturating_sub(keep);
+    Ok(auto_backups[..expired].iter()
+        .flat_map(|backup| backup_files(ctx, &backup.name))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::CliReporter;
+
+    use super::*;
+
+    fn write(path: PathBuf, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn new_lib(files: &[(&str, &str)]) -> tempdir::TempDir {
+        let lib = tempdir::TempDir::new("multimoon-lib").unwrap();
+        for (path, content) in files {
+            write(lib.path().join(path), content);
+        }
+        lib
+    }
+
+    fn new_archive(files: &[(&str, &str)]) -> zip::ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (path, content) in files {
+            zip.start_file(*path, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish_into_readable().unwrap()
+    }
+
+    const CORE: &[(&str, &str)] = &[
+        ("core/moon.mod.json", "{}"),
+        ("core/builtin/builtin.mbt", "fn f() -> Unit {}"),
+        ("core/target/build.log", "built"),
+    ];
+
+    #[tokio::test]
+    async fn digest_matches_archive_digest() {
+        let lib = new_lib(CORE);
+        let digest = digest(lib.path()).unwrap().unwrap();
+        let mut archive = archive(lib.path(), &CliReporter).await.unwrap();
+        assert_eq!(archive_digest(&mut archive).unwrap(), digest);
+
+        // build outputs are not part of the core
+        assert!(archive.file_names().all(|name| !name.starts_with("core/target")));
+        write(lib.path().join("core/target/build.log"), "rebuilt");
+        assert_eq!(super::digest(lib.path()).unwrap().unwrap(), digest);
+
+        write(lib.path().join("core/builtin/builtin.mbt"), "fn g() -> Unit {}");
+        assert_ne!(super::digest(lib.path()).unwrap().unwrap(), digest);
+    }
+
+    #[test]
+    fn digest_without_core() {
+        let lib = new_lib(&[]);
+        assert_eq!(digest(lib.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn archive_digest_ignores_non_core_files() {
+        let digest = archive_digest(&mut new_archive(&CORE[..2])).unwrap();
+        assert_eq!(archive_digest(&mut new_archive(CORE)).unwrap(), digest);
+        assert_eq!(archive_digest(&mut new_archive(&[CORE[0], CORE[1], ("other/file", "")])).unwrap(), digest);
+    }
+
+    #[tokio::test]
+    async fn replace_removes_stale_files() {
+        let source = new_lib(CORE);
+        let mut archive = archive(source.path(), &CliReporter).await.unwrap();
+        let lib = new_lib(&[("core/moon.mod.json", "{\"old\": true}"), ("core/stale/stale.mbt", "")]);
+
+        replace(lib.path(), &mut archive, &CliReporter).await.unwrap();
+        assert_eq!(digest(lib.path()).unwrap(), digest(source.path()).unwrap());
+        assert!(!lib.path().join("core/stale").exists());
+        assert_eq!(std::fs::read_dir(lib.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn replace_without_core() {
+        let lib = new_lib(&[]);
+        replace(lib.path(), &mut new_archive(&CORE[..2]), &CliReporter).await.unwrap();
+        assert_eq!(digest(lib.path()).unwrap().unwrap(), archive_digest(&mut new_archive(&CORE[..2])).unwrap());
+    }
+
+    #[tokio::test]
+    async fn replace_keeps_core_on_invalid_archive() {
+        let lib = new_lib(CORE);
+        let digest = digest(lib.path()).unwrap();
+        for mut archive in [new_archive(&[]), new_archive(&[("other/file", "")])] {
+            let err = replace(lib.path(), &mut archive, &CliReporter).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Extract(_))));
+            assert_eq!(super::digest(lib.path()).unwrap(), digest);
+            assert_eq!(std::fs::read_dir(lib.path()).unwrap().count(), 1);
+        }
+    }
+
+    fn new_backups(ctx: &Context, count: usize) -> Vec<String> {
+        (0..count).map(|_| {
+            let name = auto_backup_name(ctx);
+            save_backup(ctx, &name, new_archive(&CORE[..2]), &BackupMetadata::default()).unwrap();
+            name
+        }).collect()
+    }
+
+    #[test]
+    fn auto_backup_names_are_unique_and_ordered() {
+        let home = tempdir::TempDir::new("multimoon-home").unwrap();
+        let ctx = Context::with_home(home.path().to_path_buf());
+        let names = new_backups(&ctx, 3);
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, names);
+        assert!(names.iter().all(|name| name.starts_with(AUTO_BACKUP_PREFIX)));
+    }
+
+    #[tokio::test]
+    async fn expired_auto_backups_oldest_first() {
+        let home = tempdir::TempDir::new("multimoon-home").unwrap();
+        let ctx = Context::with_home(home.path().to_path_buf());
+        assert!(expired_auto_backups(&ctx, 0).await.unwrap().is_empty());
+
+        save_backup(&ctx, "manual", new_archive(&CORE[..2]), &BackupMetadata::default()).unwrap();
+        let names = new_backups(&ctx, 3);
+        let files = |names: &[String]| names.iter().flat_map(|name| backup_files(&ctx, name)).collect::<Vec<_>>();
+        assert_eq!(files(&names[..1]).len(), 2);
+
+        assert!(expired_auto_backups(&ctx, 3).await.unwrap().is_empty());
+        assert!(expired_auto_backups(&ctx, 5).await.unwrap().is_empty());
+        assert_eq!(expired_auto_backups(&ctx, 1).await.unwrap(), files(&names[..2]));
+        // manual backups never expire
+        assert_eq!(expired_auto_backups(&ctx, 0).await.unwrap(), files(&names));
+    }
 }
@@ -0,0 +1,147 @@
+//! Kinds of errors, and exit codes of the `multimoon` command.
+//!
+//! Operations return `anyhow` errors as usual. Failures that scripts may want to handle are raised as (or wrapped in)
+//! an [`Error`], and [`exit_code`] maps any error to a stable exit code:
+//!
+//! | code | meaning                                                                 |
+//! |------|-------------------------------------------------------------------------|
+//! | 1    | other errors                                                            |
+//! | 2    | invalid command line arguments, e.g. removing a required component      |
+//! | 3    | registry index is invalid or not supported                              |
+//! | 4    | toolchain, component, backup or core library not found                  |
+//! | 5    | network error                                                           |
+//! | 6    | checksum mismatch of a downloaded file                                  |
+//! | 7    | error reading or writing an archive (core backups, downloaded files)    |
+//! | 8    | error bundling core library                                             |
+//! | 9    | filesystem error                                                        |
+//! | 10   | cancelled, as the user didn't confirm overwriting modified files        |
+
+pub const EXIT_OTHER: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_REGISTRY: i32 = 3;
+pub const EXIT_NOT_FOUND: i32 = 4;
+pub const EXIT_NETWORK: i32 = 5;
+pub const EXIT_CHECKSUM: i32 = 6;
+pub const EXIT_EXTRACT: i32 = 7;
+pub const EXIT_BUNDLE: i32 = 8;
+pub const EXIT_FILESYSTEM: i32 = 9;
+pub const EXIT_CANCELLED: i32 = 10;
+
+/// An error of a specific kind. The message is shown to the user as is.
+#[derive(Debug)]
+pub enum Error {
+    /// Arguments are valid syntactically, but the requested operation isn't allowed.
+    Usage(String),
+    /// Registry index is invalid, not supported, or inconsistent.
+    Registry(String),
+    /// Requested toolchain, component, backup or core library doesn't exist.
+    NotFound(String),
+    /// Network request failed.
+    Network(String),
+    /// Checksum of a downloaded file doesn't match the registry.
+    Checksum(String),
+    /// Archive can't be created, decompressed or extracted.
+    Extract(String),
+    /// `moon bundle` failed.
+    Bundle(String),
+    /// Reading or writing files failed.
+    Filesystem(String),
+    /// User didn't confirm the operation.
+    Cancelled(String),
+}
+
+impl Error {
+    /// Exit code of the `multimoon` command for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => EXIT_USAGE,
+            Error::Registry(_) => EXIT_REGISTRY,
+            Error::NotFound(_) => EXIT_NOT_FOUND,
+            Error::Network(_) => EXIT_NETWORK,
+            Error::Checksum(_) => EXIT_CHECKSUM,
+            Error::Extract(_) => EXIT_EXTRACT,
+            Error::Bundle(_) => EXIT_BUNDLE,
+            Error::Filesystem(_) => EXIT_FILESYSTEM,
+            Error::Cancelled(_) => EXIT_CANCELLED,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (Error::Usage(message)
+            | Error::Registry(message)
+            | Error::NotFound(message)
+            | Error::Network(message)
+            | Error::Checksum(message)
+            | Error::Extract(message)
+            | Error::Bundle(message)
+            | Error::Filesystem(message)
+            | Error::Cancelled(message)) = self;
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Exit code for `err`: the kind of the outermost [`Error`] in its chain, otherwise derived from the underlying
+/// library error.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return err.exit_code();
+    }
+    if err.downcast_ref::<reqwest::Error>().is_some() {
+        return EXIT_NETWORK;
+    }
+    if err.downcast_ref::<zip::result::ZipError>().is_some() {
+        return EXIT_EXTRACT;
+    }
+    if err.downcast_ref::<walkdir::Error>().is_some() || err.downcast_ref::<std::io::Error>().is_some() {
+        return EXIT_FILESYSTEM;
+    }
+    EXIT_OTHER
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context as _;
+
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let io = || std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        let reqwest = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let walkdir = walkdir::WalkDir::new("/nonexistent/multimoon").into_iter().next().unwrap().unwrap_err();
+
+        let cases: Vec<(anyhow::Error, i32)> = vec![
+            (Error::Usage("usage".into()).into(), EXIT_USAGE),
+            (Error::Registry("registry".into()).into(), EXIT_REGISTRY),
+            (Error::NotFound("not found".into()).into(), EXIT_NOT_FOUND),
+            (Error::Network("network".into()).into(), EXIT_NETWORK),
+            (Error::Checksum("checksum".into()).into(), EXIT_CHECKSUM),
+            (Error::Extract("extract".into()).into(), EXIT_EXTRACT),
+            (Error::Bundle("bundle".into()).into(), EXIT_BUNDLE),
+            (Error::Filesystem("filesystem".into()).into(), EXIT_FILESYSTEM),
+            (Error::Cancelled("cancelled".into()).into(), EXIT_CANCELLED),
+            // typed errors wrapped in context
+            (anyhow::Error::from(Error::Checksum("checksum".into())).context("installing").context("updating"), EXIT_CHECKSUM),
+            (Err::<(), _>(Error::NotFound("not found".into())).context("restoring").unwrap_err(), EXIT_NOT_FOUND),
+            // the outermost typed error wins over the underlying one
+            (Err::<(), _>(io()).context(Error::Extract("extract".into())).unwrap_err(), EXIT_EXTRACT),
+            (anyhow::Error::from(Error::Network("network".into())).context(Error::Registry("registry".into())), EXIT_REGISTRY),
+            // library errors
+            (reqwest.into(), EXIT_NETWORK),
+            (zip::result::ZipError::FileNotFound.into(), EXIT_EXTRACT),
+            (walkdir.into(), EXIT_FILESYSTEM),
+            (io().into(), EXIT_FILESYSTEM),
+            (Err::<(), _>(io()).context("reading file").unwrap_err(), EXIT_FILESYSTEM),
+            // anything else
+            (anyhow::anyhow!("other"), EXIT_OTHER),
+            (anyhow::anyhow!("other").context("context"), EXIT_OTHER),
+        ];
+        for (err, code) in cases {
+            assert_eq!(exit_code(&err), code, "{:#}", err);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Fetching registry resources over HTTP(S) or from the local filesystem.
+
+use anyhow::Context as _;
+
+use crate::prelude::*;
+
+/// Parse a registry location, which can be either an URL (`https://`, `file://`, ...) or a plain
+/// directory path. The resulting URL always ends with a slash, so it can be joined with relative paths.
+pub fn parse_location(location: &str) -> Result<Url> {
+    let url = match Url::parse(location) {
+        // single letter schemes are Windows drive letters (e.g. `C:\registry`)
+        Ok(url) if url.scheme().len() > 1 => url,
+        _ => path_to_url(Path::new(location))?,
+    };
+    Ok(ensure_trailing_slash(url))
+}
+
+/// Resolve a directory location relative to `base`. The resulting URL always ends with a slash.
+pub fn resolve_location(base: &Url, location: &str) -> Result<Url> {
+    Ok(ensure_trailing_slash(resolve_file(base, location)?))
+}
+
+/// Resolve a file location relative to `base`. Absolute URLs and absolute paths are returned as is,
+/// anything else is joined to `base`.
+pub fn resolve_file(base: &Url, location: &str) -> Result<Url> {
+    match Url::parse(location) {
+        Ok(url) if url.scheme().len() > 1 => Ok(url),
+        _ if Path::new(location).is_absolute() => {
+            Url::from_file_path(location).map_err(|_| anyhow!("unsupported path {}", location))
+        },
+        _ => base.join(location)
+            .with_context(|| format!("invalid location {} relative to {}", location, base)),
+    }
+}
+
+/// Fetch the whole content of `url`, using `client` for HTTP(S) URLs and reading directly from the
+/// filesystem for `file://` URLs.
+pub async fn fetch(client: &reqwest::Client, url: &Url) -> Result<Vec<u8>> {
+    fetch_with_progress(client, url, |_, _| ()).await
+}
+
+/// Fetch the content at `url`, calling `progress` with downloaded bytes and total size (if known) as it goes.
+pub async fn fetch_with_progress<F: FnMut(u64, Option<u64>)>(client: &reqwest::Client, url: &Url, mut progress: F) -> Result<Vec<u8>> {
+    match url.scheme() {
+        "file" => {
+            let path = url.to_file_path()
+                .map_err(|_| anyhow!("invalid file url {}", url))?;
+            let content = tokio::fs::read(&path).await
+                .with_context(|| format!("error reading file {}", path.display()))?;
+            progress(content.len() as u64, Some(content.len() as u64));
+            Ok(content)
+        },
+        "http" | "https" => {
+            let mut response = client.get(url.clone()).send().await?.error_for_status()?;
+            let size = response.content_length();
+            let mut content = Vec::with_capacity(size.unwrap_or(0) as usize);
+            while let Some(chunk) = response.chunk().await? {
+                content.extend_from_slice(&chunk);
+                progress(content.len() as u64, size);
+            }
+            Ok(content)
+        },
+        scheme => Err(anyhow!("unsupported url scheme {} in {}", scheme, url)),
+    }
+}
+
+/// Size of the content at `url` without fetching it, if known. Failures are not errors, since many servers reject
+/// HEAD requests or don't tell the size.
+pub async fn content_length(client: &reqwest::Client, url: &Url) -> Option<u64> {
+    match query_content_length(client, url).await {
+        Ok(size) => size,
+        Err(err) => {
+            debug!("unable to get size of {}: {:#}", url, err);
+            None
+        },
+    }
+}
+
+async fn query_content_length(client: &reqwest::Client, url: &Url) -> Result<Option<u64>> {
+    match url.scheme() {
+        "file" => {
+            let path = url.to_file_path()
+                .map_err(|_| anyhow!("invalid file url {}", url))?;
+            let metadata = tokio::fs::metadata(&path).await
+                .with_context(|| format!("error reading file {}", path.display()))?;
+            Ok(Some(metadata.len()))
+        },
+        "http" | "https" => {
+            let response = client.head(url.clone()).send().await?.error_for_status()?;
+            Ok(response.headers().get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok()))
+        },
+        scheme => Err(anyhow!("unsupported url scheme {} in {}", scheme, url)),
+    }
+}
+
+fn path_to_url(path: &Path) -> Result<Url> {
+    let path = std::path::absolute(path)
+        .with_context(|| format!("invalid path {}", path.display()))?;
+    Url::from_directory_path(&path)
+        .map_err(|_| anyhow!("unsupported path {}", path.display()))
+}
+
+fn ensure_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_url_location() {
+        assert_eq!(parse_location("https://example.com/registry").unwrap().as_str(), "https://example.com/registry/");
+        assert_eq!(parse_location("https://example.com/registry/").unwrap().as_str(), "https://example.com/registry/");
+        assert_eq!(parse_location("file:///srv/registry").unwrap().as_str(), "file:///srv/registry/");
+    }
+
+    #[test]
+    fn parse_relative_location() {
+        let expected = Url::from_directory_path(std::env::current_dir().unwrap().join("registry")).unwrap();
+        assert_eq!(parse_location("registry").unwrap(), expected);
+        assert_eq!(parse_location("registry/").unwrap(), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_absolute_location() {
+        assert_eq!(parse_location("/srv/registry").unwrap().as_str(), "file:///srv/registry/");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn parse_windows_location() {
+        assert_eq!(parse_location(r"C:\registry").unwrap().as_str(), "file:///C:/registry/");
+        assert_eq!(parse_location("C:/registry").unwrap().as_str(), "file:///C:/registry/");
+        assert_eq!(parse_location(r"\\server\share\registry").unwrap().as_str(), "file://server/share/registry/");
+    }
+
+    #[test]
+    fn resolve_relative_file() {
+        let base = parse_location("https://example.com/registry").unwrap();
+        assert_eq!(resolve_file(&base, "toolchains/t1.zip").unwrap().as_str(), "https://example.com/registry/toolchains/t1.zip");
+        assert_eq!(resolve_file(&base, "../other/t1.zip").unwrap().as_str(), "https://example.com/other/t1.zip");
+        assert_eq!(resolve_location(&base, "toolchains").unwrap().as_str(), "https://example.com/registry/toolchains/");
+
+        let base = parse_location("file:///srv/registry").unwrap();
+        assert_eq!(resolve_file(&base, "t1.zip").unwrap().as_str(), "file:///srv/registry/t1.zip");
+    }
+
+    #[test]
+    fn resolve_absolute_file() {
+        let base = parse_location("https://example.com/registry").unwrap();
+        assert_eq!(resolve_file(&base, "https://mirror.example.com/t1.zip").unwrap().as_str(), "https://mirror.example.com/t1.zip");
+        assert_eq!(resolve_file(&base, "file:///srv/t1.zip").unwrap().as_str(), "file:///srv/t1.zip");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_absolute_path() {
+        let base = parse_location("https://example.com/registry").unwrap();
+        assert_eq!(resolve_file(&base, "/srv/t1.zip").unwrap().as_str(), "file:///srv/t1.zip");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn resolve_windows_path() {
+        let base = parse_location("https://example.com/registry").unwrap();
+        assert_eq!(resolve_file(&base, r"C:\registry\t1.zip").unwrap().as_str(), "file:///C:/registry/t1.zip");
+    }
+
+    #[tokio::test]
+    async fn content_length_unknown_on_failure() {
+        let client = reqwest::Client::new();
+        let dir = tempdir::TempDir::new("multimoon-fetch").unwrap();
+        std::fs::write(dir.path().join("file"), b"content").unwrap();
+        let base = Url::from_directory_path(dir.path()).unwrap();
+        assert_eq!(content_length(&client, &base.join("file").unwrap()).await, Some(7));
+        assert_eq!(content_length(&client, &base.join("missing").unwrap()).await, None);
+
+        // nothing listens on the port of a dropped listener
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        assert_eq!(content_length(&client, &Url::parse(&format!("http://{}/file", addr)).unwrap()).await, None);
+    }
+}
@@ -1,30 +1,7 @@
-//! The global data.
+//! Platforms supported by MultiMoon.
 
-use crate::prelude::*;
-use std::sync::OnceLock;
-
-static GLOBAL: OnceLock<GlobalInfo> = OnceLock::new();
-
-pub struct GlobalInfo {
-    pub home: PathBuf,
-    pub multimoonhome: PathBuf,
-    pub moonhome: PathBuf,
-    pub registry: Url,
-    pub verbose: bool,
-}
-
-pub fn global() -> &'static GlobalInfo {
-    return GLOBAL.get().unwrap()
-}
-
-pub fn init<F: FnOnce() -> GlobalInfo>(f: F) -> Result<()> {
-    if GLOBAL.get().is_none() {
-        GLOBAL.get_or_init(f);
-        Ok(())
-    } else {
-        Err(anyhow!("duplicate init"))
-    }
-}
+/// All platforms supported by MultiMoon registries.
+pub const ARCHS: &[&str] = &["macos_aarch64", "macos_amd64", "ubuntu_amd64", "windows_x64"];
 
 pub const fn arch() -> &'static str {
     #![allow(dead_code)]
@@ -0,0 +1,289 @@
+//! Common steps shared by installers.
+
+use std::io::Cursor;
+
+use anyhow::Context as _;
+
+use crate::prelude::*;
+use crate::error::Error;
+use crate::plan::{self, Download};
+use crate::registry::{File, Registry, Toolchain};
+use crate::report::{Event, FileKind, Reporter};
+
+pub const CORRUPT: &str = "(current installation may be corrupted)";
+
+/// Download all binaries of `toolchain` for current platform, returning decompressed and verified contents.
+pub async fn download_binaries(registry: &Registry, toolchain: &Toolchain, binaries: &[File], reporter: &Arc<dyn Reporter>) -> Result<Vec<(File, Vec<u8>)>> {
+    let client = reqwest::Client::new();
+
+    let url_prefix = binaries_url(registry, toolchain)?;
+    let index_download = Arc::new(AtomicI32::new(1));
+    let index_download_end = Arc::new(AtomicI32::new(1));
+    let mut tasks = tokio::task::JoinSet::new();
+    let binary_file_count = binaries.len();
+    for binary in binaries {
+        let index_download = index_download.clone();
+        let index_download_end = index_download_end.clone();
+        let url = crate::fetch::resolve_file(&url_prefix, &binary.downloadfrom)?;
+        let client = client.clone();
+        let binary = binary.clone();
+        let reporter = reporter.clone();
+        tasks.spawn(async move {
+            let index_download = index_download.fetch_add(1, SeqCst);
+
+            // download a binary
+            reporter.report(&Event::DownloadStarted {
+                kind: FileKind::Bin,
+                index: index_download as usize,
+                total: binary_file_count,
+                url: &url,
+            });
+            let download_start = std::time::Instant::now();
+            let compressed = crate::fetch::fetch_with_progress(&client, &url, |downloaded, size| {
+                reporter.report(&Event::DownloadProgress { url: &url, downloaded, size });
+            }).await?;
+            let download_duration = download_start.elapsed();
+            
+            // xz decompress
+            let filecontent = crate::common::xz_decompress(&compressed).with_context(|| {
+                Error::Extract(format!("error reading xz archive {}", &url))
+            })?;
+
+            // check checksum
+            let hash = crate::common::sha256_checksum(&filecontent);
+            if hash != binary.checksum {
+                return Err(Error::Checksum(format!("checksum check for {} failed!", &binary.filename)).into());
+            }
+
+            let index_download_end = index_download_end.fetch_add(1, SeqCst);
+            reporter.report(&Event::DownloadFinished {
+                kind: FileKind::Bin,
+                index: index_download_end as usize,
+                total: binary_file_count,
+                filename: &binary.filename,
+                size: compressed.len() as u64,
+                duration: download_duration,
+            });
+
+            Ok::<_, anyhow::Error>((binary, filecontent))
+        });
+    }
+    let mut binary_files = vec![];
+    while let Some(res) = tasks.join_next().await {
+        let (fileinfo, filecontent) = res.context("internal error: unable to run download task")??;
+        binary_files.push((fileinfo, filecontent));
+    }
+
+    Ok(binary_files)
+}
+
+/// Download the core library archive of `toolchain` and verify it.
+pub async fn download_core(registry: &Registry, toolchain: &Toolchain, reporter: &Arc<dyn Reporter>) -> Result<(File, zip::ZipArchive<Cursor<Vec<u8>>>)> {
+    let client = reqwest::Client::new();
+
+    let (core, url) = core_url(registry, toolchain)?;
+    let reporter = reporter.clone();
+    tokio::spawn(async move {
+        // download a binary
+        reporter.report(&Event::DownloadStarted { kind: FileKind::Lib, index: 1, total: 1, url: &url });
+        let download_start = std::time::Instant::now();
+        let zip_content = crate::fetch::fetch_with_progress(&client, &url, |downloaded, size| {
+            reporter.report(&Event::DownloadProgress { url: &url, downloaded, size });
+        }).await?;
+        let download_duration = download_start.elapsed();
+
+        // check checksum
+        let hash = crate::common::sha256_checksum(&zip_content);
+        if hash != core.checksum {
+            return Err(Error::Checksum(format!("checksum check for {} failed!", &core.filename)).into());
+        }
+
+        reporter.report(&Event::DownloadFinished {
+            kind: FileKind::Lib,
+            index: 1,
+            total: 1,
+            filename: &core.filename,
+            size: zip_content.len() as u64,
+            duration: download_duration,
+        });
+
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(zip_content))?;
+
+        Ok::<_, anyhow::Error>((core, archive))
+    }).await.context("internal error: unable to run download task")?
+}
+
+/// Check if the file at `localpath` exists and matches `checksum`.
+pub fn file_matches(localpath: &Path, file: &File) -> Result<bool> {
+    if !file.checksum.starts_with("sha256:") {
+        return Err(Error::Registry(format!("registry error: file {} has an invalid checksum", file.filename)).into());
+    }
+
+    let localfile = match std::fs::read(localpath) {
+        Ok(content) => content,
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => { 
+                return Ok(false); // doesn't match if the file is missing
+            },
+            _ => { 
+                let e = Into::<anyhow::Error>::into(err)
+                    .context(format!("error reading file {}", file.filename));
+                return Err(e); 
+            }
+        },
+    };
+    let hash = crate::common::sha256_checksum(&localfile);
+    
+    Ok(file.checksum == hash)
+}
+
+/// Write an installed binary file, with unix permission `mode`.
+pub fn write_binary(filepath: &Path, filecontent: &[u8], mode: u32) -> Result<()> {
+    let mut infile = std::io::Cursor::new(filecontent);
+    let mut outfile = std::fs::File::create(filepath)
+        .with_context(|| format!("install error: failed to create {} {}", filepath.display(), CORRUPT))?;
+    std::io::copy(&mut infile, &mut outfile)
+        .with_context(|| format!("install error: failed to write {} {}", filepath.display(), CORRUPT))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(filepath, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("install error: failed to set permission to {} {}", filepath.display(), CORRUPT))?;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = mode;
+    }
+
+    Ok(())
+}
+
+/// Remove installed binaries of removed components.
+pub fn remove_binaries(paths: &[PathBuf], reporter: &dyn Reporter) -> Result<()> {
+    for path in paths {
+        match std::fs::remove_file(path) {
+            Ok(()) => reporter.report(&Event::FileRemoved { path }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(Into::<anyhow::Error>::into(err)
+                .context(format!("failed to remove {}", path.display()))),
+        }
+    }
+    Ok(())
+}
+
+/// Remove a directory and everything in it, if it exists.
+pub fn remove_dir_if_exists(path: &Path) -> Result<()> {
+    if let Err(err) = std::fs::remove_dir_all(path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
+
+/// Run a post-install command with a clean environment (only `PATH` set to `binary_path`).
+pub fn run_command(command: &mut std::process::Command, binary_path: &Path, description: &str, reporter: &dyn Reporter) -> Result<()> {
+    command
+        .env_clear()
+        .env("PATH", binary_path);
+    let program = command.get_program().to_string_lossy().to_string();
+    let command_line = std::iter::once(program.clone())
+        .chain(command.get_args().map(|a| a.to_string_lossy().to_string()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cwd = command.get_current_dir().map(|p| p.display().to_string()).unwrap_or_else(|| ".".to_string());
+    reporter.report(&Event::CommandStarted { description, command: &command_line, cwd: &cwd });
+    let output = command
+        .output()
+        .with_context(|| format!("error running {}", &program))?;
+    reporter.report(&Event::CommandFinished {
+        program: &program,
+        success: output.status.success(),
+        stdout: &String::from_utf8_lossy(&output.stdout),
+        stderr: &String::from_utf8_lossy(&output.stderr),
+    });
+    if !output.status.success() {
+        return Err(anyhow!("failed to run {} (exit code: {})",
+            &program,
+            output.status.code().unwrap_or(-1)));
+    }
+    Ok(())
+}
+
+/// Bundle core library in `lib_path/core` with `moon bundle --all`.
+pub fn bundle_core(binary_path: &Path, lib_path: &Path, reporter: &dyn Reporter) -> Result<()> {
+    let moon_path = binary_path.join(crate::global::moon_executable_name());
+    let core_path = lib_path.join("core");
+    reporter.report(&Event::BundleStarted { core_path: &core_path });
+    let mut command = std::process::Command::new(&moon_path);
+    command.args(["bundle", "--all"])
+        .current_dir(&core_path);
+    run_command(&mut command, binary_path, "bundling core library", reporter)
+        .context(Error::Bundle("failed to bundle core library".to_string()))?;
+    reporter.report(&Event::BundleFinished { core_path: &core_path });
+    Ok(())
+}
+
+/// Plan downloading `binaries` of `toolchain`, and its core if `core`, with sizes queried from the registry.
+pub async fn plan_downloads(registry: &Registry, toolchain: &Toolchain, binaries: &[File], core: bool) -> Result<Vec<Download>> {
+    let client = reqwest::Client::new();
+    let url_prefix = binaries_url(registry, toolchain)?;
+    let mut urls = binaries.iter()
+        .map(|binary| crate::fetch::resolve_file(&url_prefix, &binary.downloadfrom))
+        .collect::<Result<Vec<_>>>()?;
+    if core {
+        urls.push(core_url(registry, toolchain)?.1);
+    }
+
+    let mut downloads = vec![];
+    for url in urls {
+        let size = crate::fetch::content_length(&client, &url).await;
+        downloads.push(Download { url: url.to_string(), size });
+    }
+    Ok(downloads)
+}
+
+/// Plan bundling core library in `lib_path/core`, see [`bundle_core`].
+pub fn plan_bundle_core(binary_path: &Path, lib_path: &Path) -> plan::Command {
+    plan::Command {
+        command: format!("{} bundle --all", binary_path.join(crate::global::moon_executable_name()).display()),
+        cwd: lib_path.join("core"),
+    }
+}
+
+/// Locations of PATH [`configure_path`] would change.
+pub fn plan_configure_path(ctx: &Context, binary_path: &Path) -> Vec<String> {
+    if !ctx.modify_path {
+        return vec![];
+    }
+    crate::shell::add_path_to_shell(ctx, binary_path, true).unwrap_or_else(|err| {
+        debug!("unable to check PATH of current shell: {:#}", err);
+        vec![]
+    })
+}
+
+fn binaries_url(registry: &Registry, toolchain: &Toolchain) -> Result<Url> {
+    Ok(Url::parse(&registry.downloadfrom)?.join(&format!("{}/{}/", toolchain.name, arch()))?)
+}
+
+fn core_url(registry: &Registry, toolchain: &Toolchain) -> Result<(File, Url)> {
+    let url_prefix = Url::parse(&registry.downloadfrom)?.join(&format!("{}/{}/", toolchain.name, "multiarch"))?;
+    let core = toolchain.core.first().context("registry error: core not found")?;
+    let url = crate::fetch::resolve_file(&url_prefix, &core.downloadfrom)?;
+    Ok((core.clone(), url))
+}
+
+/// Add `binary_path` to PATH of current shell, printing a hint on failure.
+pub fn configure_path(ctx: &Context, binary_path: &Path, reporter: &dyn Reporter) {
+    if !ctx.modify_path {
+        reporter.report(&Event::Status(&format!("skipped adding {} to PATH. (you may have to add to your PATH manually)", binary_path.display())));
+        return;
+    }
+    if let Err(e) = crate::shell::add_path_to_shell(ctx, binary_path, false) {
+        reporter.report(&Event::Warning(&format!("error adding moonbit bin path {} to current shell config: {} (you may have to add to your PATH manually)",
+            binary_path.display(),
+            e
+        )));
+    }
+}
@@ -0,0 +1,276 @@
+//! Declarative installer, with installation steps described by the registry entry itself.
+
+use anyhow::Context as _;
+
+use crate::prelude::*;
+use crate::error::Error;
+use crate::installer::{common, Installer};
+use crate::plan::{self, Plan};
+use crate::registry::{File, InstallSpec, Registry, Toolchain};
+use crate::report::{Event, FileKind, Reporter};
+
+pub struct InstDeclarative();
+
+impl InstDeclarative {
+    pub fn new() -> Self {
+        InstDeclarative()
+    }
+}
+
+impl Installer for InstDeclarative {
+    fn name(&self) -> &'static str {
+        "declarative"
+    }
+
+    fn description(&self) -> &'static str {
+        "Follows installation steps described by the `install` field of the toolchain in registry."
+    }
+
+    fn check(&self, toolchain: &Toolchain) -> Result<()> {
+        check(toolchain)
+    }
+
+    fn binary_path(&self, ctx: &Context, toolchain: &Toolchain, file: &File) -> Result<PathBuf> {
+        let (dir, _) = binary_target(spec(toolchain)?, &file.filename)?;
+        Ok(resolve(&ctx.moonhome, dir)?.join(&file.filename))
+    }
+
+    async fn matches(&self, ctx: &Context, toolchain: &Toolchain) -> Result<bool> {
+        // use checksums of binaries (of installed components) only to determine version
+        let (binaries, _) = crate::component::split_binaries(ctx, toolchain)?;
+        for binary in &binaries {
+            if !common::file_matches(&self.binary_path(ctx, toolchain, binary)?, binary)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn install(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, reporter: &Arc<dyn Reporter>) -> Result<()> {
+        let spec = spec(toolchain)?;
+        let moonhome = ctx.moonhome.clone();
+        let binary_path = resolve(&moonhome, &spec.bin_dir)?;
+        let lib_path = resolve(&moonhome, &spec.lib_dir)?;
+
+        // download all binaries (of installed components) and core from registry
+        let (binaries, removed) = crate::component::split_binaries(ctx, toolchain)?;
+        let binary_files = common::download_binaries(registry, toolchain, &binaries, reporter).await?;
+        let (core_file, mut core_archive) = common::download_core(registry, toolchain, reporter).await?;
+
+        // wipe directories
+        for dir in &spec.wipe {
+            let path = resolve_wiped(&moonhome, dir)?;
+            reporter.report(&Event::Status(&format!("removing {} ...", path.display())));
+            common::remove_dir_if_exists(&path)?;
+        }
+
+        // install all binaries
+        self.write_binaries(ctx, toolchain, &binary_files, reporter.as_ref())?;
+        let removed_paths = removed.iter().map(|f| self.binary_path(ctx, toolchain, f)).collect::<Result<Vec<_>>>()?;
+        common::remove_binaries(&removed_paths, reporter.as_ref())?;
+
+        // extract core
+        reporter.report(&Event::Status(&format!("installing [core 1 / 1] {} ...", core_file.filename)));
+        std::fs::create_dir_all(&lib_path)
+            .with_context(|| format!("install error: failed to create {} {}", lib_path.display(), common::CORRUPT))?;
+        crate::core::extract_verbose(&lib_path, &mut core_archive, &(crate::core::ExtractOptions {
+            fallback_timestamp: toolchain.last_modified
+        }), reporter.as_ref()).await?;
+        crate::core::save_installed(ctx, &crate::core::InstalledCore::new(toolchain, &mut core_archive)?)?;
+        reporter.report(&Event::Status("succesfully extracted core library."));
+
+        // run post-install commands
+        for command_spec in &spec.post_install {
+            let program = command_program(&moonhome, &binary_path, &command_spec.program)?;
+            let mut command = std::process::Command::new(&program);
+            command.args(&command_spec.args)
+                .current_dir(resolve(&moonhome, &command_spec.cwd)?);
+            if let Err(err) = common::run_command(&mut command, &binary_path, "running post-install command", reporter.as_ref()) {
+                if !command_spec.optional {
+                    return Err(err);
+                }
+                reporter.report(&Event::Warning(&format!("optional post-install command failed: {:#}", err)));
+            }
+        }
+
+        common::configure_path(ctx, &binary_path, reporter.as_ref());
+
+        reporter.report(&Event::Status(&format!("sucessfully installed toolchain {}.", &toolchain.name)));
+
+        Ok(())
+    }
+
+    async fn install_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File], reporter: &Arc<dyn Reporter>) -> Result<()> {
+        let binary_files = common::download_binaries(registry, toolchain, files, reporter).await?;
+        self.write_binaries(ctx, toolchain, &binary_files, reporter.as_ref())
+    }
+
+    async fn plan(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain) -> Result<Plan> {
+        let spec = spec(toolchain)?;
+        let moonhome = ctx.moonhome.as_path();
+        let binary_path = resolve(moonhome, &spec.bin_dir)?;
+        let lib_path = resolve(moonhome, &spec.lib_dir)?;
+        let (binaries, removed) = crate::component::split_binaries(ctx, toolchain)?;
+        let core = toolchain.core.first().context("registry error: core not found")?;
+
+        let wiped = spec.wipe.iter().map(|dir| resolve_wiped(moonhome, dir)).collect::<Result<Vec<_>>>()?;
+        let removed_paths = removed.iter().map(|f| self.binary_path(ctx, toolchain, f)).collect::<Result<Vec<_>>>()?;
+        let mut commands = vec![];
+        for command_spec in &spec.post_install {
+            let program = command_program(moonhome, &binary_path, &command_spec.program)?;
+            commands.push(plan::Command {
+                command: std::iter::once(program.display().to_string())
+                    .chain(command_spec.args.iter().cloned())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                cwd: resolve(moonhome, &command_spec.cwd)?,
+            });
+        }
+        Ok(Plan {
+            resolved: Some(format!("{} [{}] (installer {})", &toolchain.name, &toolchain.moonver, self.name())),
+            downloads: common::plan_downloads(registry, toolchain, &binaries, true).await?,
+            writes: binaries.iter().map(|f| self.binary_path(ctx, toolchain, f))
+                .chain([Ok(crate::core::installed_path(ctx))])
+                .collect::<Result<Vec<_>>>()?,
+            removes: plan::existing(wiped.into_iter().chain(removed_paths)),
+            extracts: vec![plan::Extract { archive: core.filename.clone(), to: lib_path, files: None }],
+            shell_configs: common::plan_configure_path(ctx, &binary_path),
+            commands,
+        })
+    }
+
+    async fn plan_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File]) -> Result<Plan> {
+        Ok(Plan {
+            downloads: common::plan_downloads(registry, toolchain, files, false).await?,
+            writes: files.iter().map(|f| self.binary_path(ctx, toolchain, f)).collect::<Result<Vec<_>>>()?,
+            ..Plan::default()
+        })
+    }
+}
+
+impl InstDeclarative {
+    fn write_binaries(&self, ctx: &Context, toolchain: &Toolchain, binary_files: &[(File, Vec<u8>)], reporter: &dyn Reporter) -> Result<()> {
+        let spec = spec(toolchain)?;
+        let binary_file_count = binary_files.len();
+        for (index, (fileinfo, filecontent)) in binary_files.iter().enumerate() {
+            let (_, mode) = binary_target(spec, &fileinfo.filename)?;
+            let filepath = self.binary_path(ctx, toolchain, fileinfo)?;
+            if let Some(dirpath) = filepath.parent() {
+                std::fs::create_dir_all(dirpath)
+                    .with_context(|| format!("install error: failed to create {} {}", dirpath.display(), common::CORRUPT))?;
+            }
+            common::write_binary(&filepath, filecontent, mode)?;
+            reporter.report(&Event::FileInstalled {
+                kind: FileKind::Bin,
+                index: index + 1,
+                total: binary_file_count,
+                path: &filepath,
+            });
+        }
+        reporter.report(&Event::Status("succesfully installed binaries."));
+        Ok(())
+    }
+}
+
+/// Check the installation steps of `toolchain` without installing anything.
+fn check(toolchain: &Toolchain) -> Result<()> {
+    let spec = spec(toolchain)?;
+    let moonhome = PathBuf::from("moonhome");
+    for dir in &spec.wipe {
+        resolve_wiped(&moonhome, dir)?;
+    }
+    for dir in [&spec.bin_dir, &spec.lib_dir] {
+        resolve(&moonhome, dir)?;
+    }
+    for binary in &toolchain.bin {
+        let (dir, _) = binary_target(spec, &binary.filename)?;
+        resolve(&moonhome, dir)?;
+    }
+    for command_spec in &spec.post_install {
+        resolve(&moonhome, &command_spec.cwd)?;
+        if command_spec.program.contains(['/', '\\']) {
+            resolve(&moonhome, &command_spec.program)?;
+        }
+    }
+    Ok(())
+}
+
+fn spec(toolchain: &Toolchain) -> Result<&InstallSpec> {
+    toolchain.install.as_ref()
+        .with_context(|| format!("registry error: toolchain {} has no installation steps", &toolchain.name))
+}
+
+/// Directory and unix permissions of an installed binary.
+fn binary_target<'a>(spec: &'a InstallSpec, filename: &str) -> Result<(&'a str, u32)> {
+    let file_spec = spec.files.iter().find(|f| f.filename == filename);
+    let dir = file_spec.and_then(|f| f.dir.as_deref()).unwrap_or(&spec.bin_dir);
+    let mode = file_spec.and_then(|f| f.mode.as_deref()).unwrap_or(&spec.bin_mode);
+    let mode = u32::from_str_radix(mode, 8)
+        .with_context(|| format!("registry error: invalid permissions {} of file {}", mode, filename))?;
+    Ok((dir, mode))
+}
+
+/// Path of post-install command `program`, a bare name is looked up in `binary_path`.
+fn command_program(moonhome: &Path, binary_path: &Path, program: &str) -> Result<PathBuf> {
+    if program.contains(['/', '\\']) {
+        resolve(moonhome, program)
+    } else {
+        Ok(binary_path.join(format!("{}{}", program, std::env::consts::EXE_SUFFIX)))
+    }
+}
+
+/// Resolve a path relative to MoonBit home directory, rejecting any path outside of it.
+fn resolve(moonhome: &Path, relative: &str) -> Result<PathBuf> {
+    use std::path::Component;
+    let path = Path::new(relative);
+    if !path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(Error::Registry(format!("registry error: path {} is not within MoonBit home directory", relative)).into());
+    }
+    Ok(moonhome.join(path))
+}
+
+/// Resolve a directory to wipe like [`resolve`], also rejecting MoonBit home directory itself (e.g. `.` or an empty
+/// path), which would remove the whole installation.
+fn resolve_wiped(moonhome: &Path, relative: &str) -> Result<PathBuf> {
+    let path = resolve(moonhome, relative)?;
+    if !Path::new(relative).components().any(|c| matches!(c, std::path::Component::Normal(_))) {
+        return Err(Error::Registry(format!("registry error: cannot wipe {}, which is MoonBit home directory itself", relative)).into());
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_within_moonhome() {
+        let moonhome = Path::new("moonhome");
+        assert_eq!(resolve(moonhome, "bin").unwrap(), moonhome.join("bin"));
+        assert_eq!(resolve(moonhome, "./lib/core").unwrap(), moonhome.join("lib").join("core"));
+        assert_eq!(resolve(moonhome, ".").unwrap(), moonhome.join("."));
+    }
+
+    #[test]
+    fn resolve_rejects_paths_outside_moonhome() {
+        let moonhome = Path::new("moonhome");
+        for relative in ["..", "a/../..", "bin/..", "/etc", "/"] {
+            assert!(resolve(moonhome, relative).is_err(), "{} should be rejected", relative);
+        }
+        #[cfg(windows)]
+        for relative in ["C:\\Windows", "\\Windows", "..\\x"] {
+            assert!(resolve(moonhome, relative).is_err(), "{} should be rejected", relative);
+        }
+    }
+
+    #[test]
+    fn resolve_wiped_rejects_moonhome_itself() {
+        let moonhome = Path::new("moonhome");
+        for relative in ["", ".", "./.", "..", "a/../..", "/", "/etc"] {
+            assert!(resolve_wiped(moonhome, relative).is_err(), "{:?} should be rejected", relative);
+        }
+        assert_eq!(resolve_wiped(moonhome, "lib/core").unwrap(), moonhome.join("lib").join("core"));
+        assert_eq!(resolve_wiped(moonhome, "./bin").unwrap(), moonhome.join("bin"));
+    }
+}
@@ -1,11 +1,12 @@
 //! Initial installer since 2024-05-07 toolchain.
 
-use std::io::Read;
-
-use anyhow::Context;
+use anyhow::Context as _;
 
 use crate::prelude::*;
-use crate::installer::Installer;
+use crate::installer::{common, Installer};
+use crate::plan::{self, Plan};
+use crate::registry::{File, Registry, Toolchain};
+use crate::report::{Event, FileKind, Reporter};
 
 pub struct InstInitial();
 
@@ -16,34 +17,27 @@ impl InstInitial {
 }
 
 impl Installer for InstInitial {
-    async fn matches(&self, toolchain: &crate::registry::Toolchain) -> Result<bool> {
-        let moonhome = global().moonhome.clone();
+    fn name(&self) -> &'static str {
+        "initial"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["2024-05-07"]
+    }
 
-        // use checksums of `.moon/bin/*` only to determine version (ignore `.moon/lib/core`)
-        for binary in &toolchain.bin {
-            use sha2::{Sha256, Digest};
+    fn description(&self) -> &'static str {
+        "Installs binaries to `bin` and core to `lib/core`, then bundles core. (toolchains since 2024-05-07)"
+    }
 
-            if !binary.checksum.starts_with("sha256:") {
-                return Err(anyhow!("registry error: file {} has an invalid checksum", binary.filename));
-            }
+    fn binary_path(&self, ctx: &Context, _toolchain: &Toolchain, file: &File) -> Result<PathBuf> {
+        Ok(ctx.moonhome.join("bin").join(&file.filename))
+    }
 
-            let localpath = moonhome.join("bin").join(&binary.filename);
-            let localfile = match std::fs::read(&localpath) {
-                Ok(content) => content,
-                Err(err) => match err.kind() {
-                    std::io::ErrorKind::NotFound => { 
-                        return Ok(false); // doesn't match if some binary files missing
-                    },
-                    _ => { 
-                        let e = Into::<anyhow::Error>::into(err)
-                            .context(format!("error reading file {}", binary.filename));
-                        return Err(e); 
-                    }
-                },
-            };
-            let hash = format!("sha256:{}", base16ct::lower::encode_string(&Sha256::digest(&localfile)));
-            
-            if !(binary.checksum == hash) {
+    async fn matches(&self, ctx: &Context, toolchain: &Toolchain) -> Result<bool> {
+        // use checksums of `.moon/bin/*` only to determine version (ignore `.moon/lib/core` and removed components)
+        let (binaries, _) = crate::component::split_binaries(ctx, toolchain)?;
+        for binary in &binaries {
+            if !common::file_matches(&self.binary_path(ctx, toolchain, binary)?, binary)? {
                 return Ok(false);
             }
         }
@@ -51,248 +45,96 @@ impl Installer for InstInitial {
         return Ok(true);
     }
 
-    async fn install(&self, registry: &crate::registry::Registry, toolchain: &crate::registry::Toolchain) -> Result<()> {
-        let client = reqwest::Client::new();
-
-        // download all binaries from registry
-        let url_prefix = Url::parse(&registry.downloadfrom)?.join(&format!("{}/{}/", toolchain.name, arch()))?;
-        let index_download = Arc::new(AtomicI32::new(1));
-        let index_download_end = Arc::new(AtomicI32::new(1));
-        let mut tasks = tokio::task::JoinSet::new();
-        let binary_file_count = toolchain.bin.len();
-        for binary in &toolchain.bin {
-            let index_download = index_download.clone();
-            let index_download_end = index_download_end.clone();
-            let url = url_prefix.join(&binary.downloadfrom)?;
-            let client = client.clone();
-            let binary = binary.clone();
-            tasks.spawn(async move {
-                use sha2::{Sha256, Digest};
-                let index_download = index_download.fetch_add(1, SeqCst);
-
-                // download a binary
-                println!("downloading [bin {} / {}] {} ...", 
-                    index_download,
-                    binary_file_count,
-                    &url,
-                );
-                let download_start = std::time::Instant::now();
-                let response = client.get(url.clone()).send().await?.error_for_status()?;
-                let download_duration = download_start.elapsed();
-                let compressed = response.bytes().await?;
-                
-                // xz decompress
-                let mut xzdecoder = xz2::bufread::XzDecoder::new(&compressed[..]);
-                let mut filecontent = vec![];
-                xzdecoder.read_to_end(&mut filecontent).with_context(|| {
-                    anyhow!("error reading xz archive {}", &url)
-                })?;
-
-                // check checksum
-                let hash = format!("sha256:{}", base16ct::lower::encode_string(&Sha256::digest(&filecontent)));
-                if hash != binary.checksum {
-                    return Err(anyhow!("checksum check for {} failed!", &binary.filename));
-                }
-
-                let index_download_end = index_download_end.fetch_add(1, SeqCst);
-                println!("downloaded [bin {} / {}] {} ({:.2} KiB/s) ...", 
-                    index_download_end,
-                    binary_file_count,
-                    &binary.filename,
-                    (compressed.len() as f64) / download_duration.as_secs_f64() / 1024f64,
-                );
-
-                Ok::<_, anyhow::Error>((binary, filecontent))
-            });
-        }
-        let mut binary_files = vec![];
-        while let Some(res) = tasks.join_next().await {
-            let (fileinfo, filecontent) = res.context("internal error: unable to run download task")??;
-            binary_files.push((fileinfo, filecontent));
-        }
-
-        // download core from registry
-        let url_prefix = Url::parse(&registry.downloadfrom)?.join(&format!("{}/{}/", toolchain.name, "multiarch"))?;
-        let (core_file, mut core_archive) = {
-            let core = toolchain.core.get(0).context("registry error: core not found")?;
-            let url = url_prefix.join(&core.downloadfrom)?;
-            let client = client.clone();
-            let core = core.clone();
-            tokio::spawn(async move {
-                use sha2::{Sha256, Digest};
-
-                // download a binary
-                println!("downloading [lib 1 / 1] {} ...", &url);
-                let download_start = std::time::Instant::now();
-                let response = client.get(url).send().await?.error_for_status()?;
-                let download_duration = download_start.elapsed();
-                let zip_content = response.bytes().await?;
-
-                // check checksum
-                let hash = format!("sha256:{}", base16ct::lower::encode_string(&Sha256::digest(&zip_content)));
-                if hash != core.checksum {
-                    return Err(anyhow!("checksum check for {} failed!", &core.filename));
-                }
-
-                println!("downloaded [lib 1 / 1] {} ({:.2} KiB/s) ...", 
-                    &core.filename,
-                    (zip_content.len() as f64) / download_duration.as_secs_f64() / 1024f64,
-                );
-
-                let archive = zip::ZipArchive::new(std::io::Cursor::new(zip_content))?;
-
-                Ok::<_, anyhow::Error>((core, archive))
-            }).await.context("internal error: unable to run download task")??
-        };
+    async fn install(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, reporter: &Arc<dyn Reporter>) -> Result<()> {
+        // download all binaries (of installed components) and core from registry
+        let (binaries, removed) = crate::component::split_binaries(ctx, toolchain)?;
+        let binary_files = common::download_binaries(registry, toolchain, &binaries, reporter).await?;
+        let (core_file, mut core_archive) = common::download_core(registry, toolchain, reporter).await?;
 
         // install all binaries to moonhome/bin
-        let moonhome = global().moonhome.clone();
+        let moonhome = ctx.moonhome.clone();
         let binary_path = moonhome.join("bin");
-        std::fs::create_dir_all(&binary_path)?;
-        const CORRUPT: &'static str = "(current installation may be corrupted)";
-        for (index, (fileinfo, filecontent)) in binary_files.iter().enumerate() {
-            let filepath = binary_path.join(&fileinfo.filename);
-            println!("installing [bin {} / {}] {} ...", 
-                index + 1,
-                binary_file_count,
-                filepath.display());
-            let mut infile = std::io::Cursor::new(filecontent);
-            let mut outfile = std::fs::File::create(&filepath)
-                .with_context(|| format!("install error: failed to create {} {}", filepath.display(), CORRUPT))?;
-            std::io::copy(&mut infile, &mut outfile)
-                .with_context(|| format!("install error: failed to write {} {}", filepath.display(), CORRUPT))?;
-
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                std::fs::set_permissions(&filepath, std::fs::Permissions::from_mode(0o755))
-                    .with_context(|| format!("install error: failed to set permission to {} {}", filepath.display(), CORRUPT))?;
-            }
-        }
-        println!("succesfully installed binaries.");
+        self.write_binaries(ctx, toolchain, &binary_files, reporter.as_ref())?;
+        let removed_paths = removed.iter().map(|f| self.binary_path(ctx, toolchain, f)).collect::<Result<Vec<_>>>()?;
+        common::remove_binaries(&removed_paths, reporter.as_ref())?;
 
         // removing old core in moonhome/lib
-        println!("installing [core 1 / 1] {} ...", core_file.filename);
+        reporter.report(&Event::Status(&format!("installing [core 1 / 1] {} ...", core_file.filename)));
         let lib_path = moonhome.join("lib");
         std::fs::create_dir_all(&lib_path)?;
         let lib_core_path = lib_path.join("core");
         
-        println!("removing old core in {} ...", lib_core_path.display());
-        if let Err(err) = std::fs::remove_dir_all(&lib_core_path) {
-            if !(err.kind() == std::io::ErrorKind::NotFound) {
-                return Err(err.into());
-            }
-        }
+        reporter.report(&Event::Status(&format!("removing old core in {} ...", lib_core_path.display())));
+        common::remove_dir_if_exists(&lib_core_path)?;
         std::fs::create_dir_all(&lib_core_path)?;
 
         // extract core to moonhome/lib
         crate::core::extract_verbose(&lib_path, &mut core_archive, &(crate::core::ExtractOptions {
             fallback_timestamp: toolchain.last_modified
-        })).await?;
-        println!("succesfully extracted core library.");
+        }), reporter.as_ref()).await?;
+        crate::core::save_installed(ctx, &crate::core::InstalledCore::new(toolchain, &mut core_archive)?)?;
+        reporter.report(&Event::Status("succesfully extracted core library."));
 
         // bundle core in moonhome/lib
-        let moon_path = binary_path.join(crate::global::moon_executable_name());
-        let core_path = lib_path.join("core");
-        let mut command = std::process::Command::new(&moon_path);
-        command.args(["bundle", "--all"])
-            .current_dir(&core_path)
-            .env_clear()
-            .env("PATH", &binary_path);
-        println!("bundling core library (run {} in {})", {
-            let mut r = command.get_program().to_string_lossy().to_string();
-            for command_arg in command.get_args() {
-                r.push_str(" ");
-                r.push_str(&command_arg.to_string_lossy());
-            }
-            r
-        }, command.get_current_dir().unwrap().display());
-        let output = command
-            .output()
-            .context("error bundling core library")?;
-        if (!output.status.success()) || global().verbose {
-            use std::io::Write;
-            let _ = std::io::stdout().write_all(&output.stdout);
-            let _ = std::io::stderr().write_all(&output.stderr);
-        }
-        if !output.status.success() {
-            return Err(anyhow!("failed to bundle core library (exit code: {})",
-                output.status.code().unwrap_or(-1)));
-        }
-        println!("succesfully bundled core library.");
-        println!("succesfully installed libraries.");
+        common::bundle_core(&binary_path, &lib_path, reporter.as_ref())?;
+        reporter.report(&Event::Status("succesfully installed libraries."));
 
-        if let Err(e) = add_path_to_shell(&binary_path) {
-            println!("error adding moonbit bin path {} to current shell config: {}",
-                binary_path.display(),
-                e
-            );
-            println!(" (you may have to add to your PATH manually)");
-        }
+        common::configure_path(ctx, &binary_path, reporter.as_ref());
 
-        println!("sucessfully installed toolchain {}.", &toolchain.name);
+        reporter.report(&Event::Status(&format!("sucessfully installed toolchain {}.", &toolchain.name)));
 
         Ok(())
     }
-}
-
-fn add_path_to_shell<P: AsRef<std::path::Path>>(path: P) -> Result<()> {
-    #[cfg(windows)]
-    {
-        use winreg::{enums::*, RegKey};
-        let path_str = path.as_ref().to_str().context("unsupported path name")?;
-
-        const ERR_READ: &'static str = "cannot read registry";
-        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-        let env = hkcu.open_subkey_with_flags("Environment", KEY_QUERY_VALUE | KEY_SET_VALUE)
-            .context(ERR_READ)?;
-        let path = env.get_value::<String, _>("Path").context(ERR_READ)?;
-
-        if path.contains(path_str) {
-            println!("{} has already been configured in user PATH environment variable.", path_str);
-        } else {
-            println!("adding {} to user PATH environment variable", path_str);
-            const ERR_WRITE: &'static str = "cannot write registry";
-            let path_new = format!("{};{}", path_str, &path);
-            env.set_value("Path", &path_new).context(ERR_WRITE)?;
-        }
 
-        Ok(())
+    async fn install_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File], reporter: &Arc<dyn Reporter>) -> Result<()> {
+        let binary_files = common::download_binaries(registry, toolchain, files, reporter).await?;
+        self.write_binaries(ctx, toolchain, &binary_files, reporter.as_ref())
     }
 
-    #[cfg(unix)]
-    {
-        let path_str = path.as_ref().to_str().context("unsupported path name")?;
-
-        const ERR_DETECT: &'static str = "cannot detect current shell";
-        let shell_path = PathBuf::from(std::env::var("SHELL").context(ERR_DETECT)?);
-        let shell_filename = shell_path.file_name().context(ERR_DETECT)?.to_str().context(ERR_DETECT)?;
-        let shell_config_path = global().home.join(match shell_filename {
-            "bash" => ".bashrc",
-            "zsh" => ".zshrc",
-            "fish" => ".config/fish/config.fish",
-            _ => ".profile",
-        });
-        let shell_config_path_str = shell_config_path.to_str().context("unsupported path name")?;
+    async fn plan(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain) -> Result<Plan> {
+        let (binaries, removed) = crate::component::split_binaries(ctx, toolchain)?;
+        let binary_path = ctx.moonhome.join("bin");
+        let lib_path = ctx.moonhome.join("lib");
+        let core = toolchain.core.first().context("registry error: core not found")?;
+
+        let removed_paths = removed.iter().map(|f| self.binary_path(ctx, toolchain, f)).collect::<Result<Vec<_>>>()?;
+        Ok(Plan {
+            resolved: Some(format!("{} [{}] (installer {})", &toolchain.name, &toolchain.moonver, self.name())),
+            downloads: common::plan_downloads(registry, toolchain, &binaries, true).await?,
+            writes: binaries.iter().map(|f| self.binary_path(ctx, toolchain, f))
+                .chain([Ok(crate::core::installed_path(ctx))])
+                .collect::<Result<Vec<_>>>()?,
+            removes: plan::existing(removed_paths.into_iter().chain([lib_path.join("core")])),
+            extracts: vec![plan::Extract { archive: core.filename.clone(), to: lib_path.clone(), files: None }],
+            shell_configs: common::plan_configure_path(ctx, &binary_path),
+            commands: vec![common::plan_bundle_core(&binary_path, &lib_path)],
+        })
+    }
 
-        const ERR_READ: &'static str = "cannot read shell config file";
-        let mut shell_config_content = std::fs::read_to_string(&shell_config_path).context(ERR_READ)?;
+    async fn plan_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File]) -> Result<Plan> {
+        Ok(Plan {
+            downloads: common::plan_downloads(registry, toolchain, files, false).await?,
+            writes: files.iter().map(|f| self.binary_path(ctx, toolchain, f)).collect::<Result<Vec<_>>>()?,
+            ..Plan::default()
+        })
+    }
+}
 
-        if shell_config_content.contains(path_str) {
-            println!("{} has already been configured in PATH of shell config {}.", path_str, shell_config_path_str);
-        } else {
-            println!("adding {} to the PATH of current shell config: {}", path_str, shell_config_path_str);
-            const ERR_WRITE: &'static str = "cannot write shell config file";
-            shell_config_content.push('\n');
-            shell_config_content.push_str(&format!("export PATH=\"{}:$PATH\"\n", path_str));
-            std::fs::write(&shell_config_path, &shell_config_content).context(ERR_WRITE)?;
+impl InstInitial {
+    fn write_binaries(&self, ctx: &Context, toolchain: &Toolchain, binary_files: &[(File, Vec<u8>)], reporter: &dyn Reporter) -> Result<()> {
+        std::fs::create_dir_all(ctx.moonhome.join("bin"))?;
+        let binary_file_count = binary_files.len();
+        for (index, (fileinfo, filecontent)) in binary_files.iter().enumerate() {
+            let filepath = self.binary_path(ctx, toolchain, fileinfo)?;
+            common::write_binary(&filepath, filecontent, 0o755)?;
+            reporter.report(&Event::FileInstalled {
+                kind: FileKind::Bin,
+                index: index + 1,
+                total: binary_file_count,
+                path: &filepath,
+            });
         }
-
+        reporter.report(&Event::Status("succesfully installed binaries."));
         Ok(())
     }
-
-    #[cfg(not(any(windows, unix)))]
-    {
-        compile_error!("unsupported platform")
-    }
 }
@@ -1,19 +1,156 @@
 //! Operations for installing MoonBit toolchain.
 
+mod common;
+mod inst_declarative;
 mod inst_initial;
 
+pub use common::{bundle_core, configure_path, plan_bundle_core, plan_configure_path, remove_dir_if_exists};
+
 use crate::prelude::*;
-use crate::registry::Toolchain;
+use crate::error::Error;
+use crate::plan::Plan;
+use crate::registry::{File, Registry, Toolchain};
+use crate::report::Reporter;
 
+// installers are dispatched statically through `AnyInstaller`, so `Send` bounds of the futures are not a concern
+#[allow(async_fn_in_trait)]
 pub trait Installer
 {
-    async fn matches(&self, toolchain: &Toolchain) -> Result<bool>;
-    async fn install(&self, registry: &crate::registry::Registry, toolchain: &crate::registry::Toolchain) -> Result<()>;
+    /// Name of the installer, as referred by the `installer` field of registry toolchains.
+    fn name(&self) -> &'static str;
+
+    /// Other names referring to this installer.
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Platforms supported by this installer.
+    fn platforms(&self) -> &'static [&'static str] {
+        crate::global::ARCHS
+    }
+
+    /// A short description of this installer.
+    fn description(&self) -> &'static str;
+
+    /// Check if `toolchain` is well-formed for this installer, without installing anything.
+    fn check(&self, _toolchain: &Toolchain) -> Result<()> {
+        Ok(())
+    }
+
+    /// Path where binary `file` of `toolchain` is installed to.
+    fn binary_path(&self, ctx: &Context, toolchain: &Toolchain, file: &File) -> Result<PathBuf>;
+
+    /// Check if `toolchain` is the one installed in MoonBit home directory.
+    async fn matches(&self, ctx: &Context, toolchain: &Toolchain) -> Result<bool>;
+
+    /// Install `toolchain` to MoonBit home directory.
+    async fn install(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, reporter: &Arc<dyn Reporter>) -> Result<()>;
+
+    /// Install only the binaries `files` of `toolchain`, used for adding components.
+    async fn install_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File], reporter: &Arc<dyn Reporter>) -> Result<()>;
+
+    /// Changes [`Installer::install`] would make, without changing anything.
+    async fn plan(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain) -> Result<Plan>;
+
+    /// Changes [`Installer::install_binaries`] would make, without changing anything.
+    async fn plan_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File]) -> Result<Plan>;
+}
+
+/// Any of the installers known to MultiMoon.
+pub enum AnyInstaller {
+    Initial(inst_initial::InstInitial),
+    Declarative(inst_declarative::InstDeclarative),
 }
 
-pub fn get_installer(name: &str) -> Result<impl Installer> {
-    match name {
-        "initial" | "2024-05-07" => Ok(inst_initial::InstInitial::new()),
-        _ => Err(anyhow!("registry error: unknown installer {} (a new version of MultiMoon may be needed?)", name)),
+macro_rules! dispatch {
+    ($self:ident, $i:ident => $e:expr) => {
+        match $self {
+            AnyInstaller::Initial($i) => $e,
+            AnyInstaller::Declarative($i) => $e,
+        }
+    };
+}
+
+impl Installer for AnyInstaller {
+    fn name(&self) -> &'static str {
+        dispatch!(self, i => i.name())
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        dispatch!(self, i => i.aliases())
+    }
+
+    fn platforms(&self) -> &'static [&'static str] {
+        dispatch!(self, i => i.platforms())
+    }
+
+    fn description(&self) -> &'static str {
+        dispatch!(self, i => i.description())
+    }
+
+    fn check(&self, toolchain: &Toolchain) -> Result<()> {
+        dispatch!(self, i => i.check(toolchain))
+    }
+
+    fn binary_path(&self, ctx: &Context, toolchain: &Toolchain, file: &File) -> Result<PathBuf> {
+        dispatch!(self, i => i.binary_path(ctx, toolchain, file))
+    }
+
+    async fn matches(&self, ctx: &Context, toolchain: &Toolchain) -> Result<bool> {
+        dispatch!(self, i => i.matches(ctx, toolchain).await)
+    }
+
+    async fn install(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, reporter: &Arc<dyn Reporter>) -> Result<()> {
+        dispatch!(self, i => i.install(ctx, registry, toolchain, reporter).await)
+    }
+
+    async fn install_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File], reporter: &Arc<dyn Reporter>) -> Result<()> {
+        dispatch!(self, i => i.install_binaries(ctx, registry, toolchain, files, reporter).await)
+    }
+
+    async fn plan(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain) -> Result<Plan> {
+        dispatch!(self, i => i.plan(ctx, registry, toolchain).await)
+    }
+
+    async fn plan_binaries(&self, ctx: &Context, registry: &Registry, toolchain: &Toolchain, files: &[File]) -> Result<Plan> {
+        dispatch!(self, i => i.plan_binaries(ctx, registry, toolchain, files).await)
+    }
+}
+
+/// All installers known to MultiMoon.
+pub fn installers() -> Vec<AnyInstaller> {
+    vec![
+        AnyInstaller::Initial(inst_initial::InstInitial::new()),
+        AnyInstaller::Declarative(inst_declarative::InstDeclarative::new()),
+    ]
+}
+
+pub fn get_installer(name: &str) -> Result<AnyInstaller> {
+    installers().into_iter()
+        .find(|i| i.name() == name || i.aliases().contains(&name))
+        .ok_or_else(|| Error::Registry(format!("registry error: unknown installer {} (a new version of MultiMoon may be needed?)", name)).into())
+}
+
+/// Check if `toolchain` can be installed on platform `arch` by its installer, without installing anything.
+pub fn check_toolchain(toolchain: &Toolchain, arch: &str) -> Result<()> {
+    let installer = get_installer(&toolchain.installer)?;
+    if !installer.platforms().contains(&arch) {
+        return Err(Error::Registry(format!("registry error: installer {} of toolchain {} doesn't support platform {}",
+            installer.name(), &toolchain.name, arch)).into());
+    }
+    installer.check(toolchain)
+}
+
+/// Find the currently installed toolchain in `registry`, checking latest toolchains first.
+pub async fn current_toolchain(ctx: &Context, registry: &Registry) -> Result<Option<Toolchain>> {
+    let mut toolchains = registry.toolchains.clone();
+    toolchains.sort_by_key(|t| std::cmp::Reverse(t.last_modified));
+
+    for toolchain in toolchains.into_iter().filter(|t| t.is_supported()) {
+        let installer = get_installer(&toolchain.installer)?;
+        if installer.matches(ctx, &toolchain).await? {
+            return Ok(Some(toolchain));
+        }
     }
+    Ok(None)
 }
@@ -0,0 +1,23 @@
+//! MultiMoon - an installer of MoonBit language toolchain.
+//!
+//! Besides the `multimoon` command, MultiMoon can be used as a library: the registry client ([`registry`]),
+//! installers and toolchain resolution ([`installer`]), and core library backups ([`core`]). All operations take an
+//! explicit [`Context`] with the locations of MoonBit and MultiMoon, and long running operations report their
+//! progress to a [`report::Reporter`].
+
+pub mod common;
+pub mod component;
+pub mod context;
+pub mod core;
+pub mod error;
+pub mod fetch;
+pub mod global;
+pub mod installer;
+pub mod logging;
+pub mod plan;
+pub mod prelude;
+pub mod registry;
+pub mod report;
+pub mod shell;
+
+pub use context::Context;
@@ -0,0 +1,141 @@
+//! Leveled logging to the console, and to a log file of each run under MultiMoon storage dir.
+//!
+//! Info messages are the normal progress output, printed to stdout (or stderr in JSON output format). Warnings,
+//! errors and verbose messages are printed to stderr. The log file keeps verbose messages regardless of the
+//! console level, including full output of commands run by installers.
+
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{context::OutputFormat, prelude::*};
+
+/// Number of log files kept in the log directory.
+const LOG_FILES_KEPT: usize = 20;
+
+struct Logger {
+    /// Level of messages printed to console.
+    console_level: LevelFilter,
+    /// Show messages of dependencies too.
+    all_targets: bool,
+    info_to_stderr: bool,
+    color_stdout: bool,
+    color_stderr: bool,
+    log_dir: PathBuf,
+    /// Opened on the first message, `Err` if it cannot be created.
+    file: Mutex<Option<std::result::Result<std::fs::File, ()>>>,
+}
+
+static LOGGER: std::sync::OnceLock<Logger> = std::sync::OnceLock::new();
+
+/// Initialize logging with verbosity `verbose` (count of `-v`), or only warnings and errors if `quiet`. No log file is
+/// written without `multimoonhome`.
+pub fn init(verbose: u8, quiet: bool, format: OutputFormat, multimoonhome: Option<&Path>) {
+    let console_level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let logger = LOGGER.get_or_init(|| Logger {
+        console_level,
+        all_targets: verbose >= 3,
+        info_to_stderr: format != OutputFormat::Text,
+        color_stdout: !no_color && std::io::stdout().is_terminal(),
+        color_stderr: !no_color && std::io::stderr().is_terminal(),
+        log_dir: multimoonhome.map(|path| path.join("logs")).unwrap_or_default(),
+        file: Mutex::new(if multimoonhome.is_some() { None } else { Some(Err(())) }),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(std::cmp::max(console_level, LevelFilter::Debug));
+    }
+}
+
+/// Check if messages of `level` are printed to console.
+pub fn console_enabled(level: Level) -> bool {
+    LOGGER.get().is_some_and(|logger| level <= logger.console_level)
+}
+
+impl Logger {
+    fn is_own(&self, metadata: &Metadata) -> bool {
+        self.all_targets || metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn write_console(&self, record: &Record) {
+        let (prefix, color) = match record.level() {
+            Level::Error => ("error: ", "\x1b[1;31m"),
+            Level::Warn => ("warning: ", "\x1b[1;33m"),
+            Level::Info => ("", ""),
+            Level::Debug => ("", "\x1b[2m"),
+            Level::Trace => ("trace: ", "\x1b[2m"),
+        };
+        let to_stdout = record.level() == Level::Info && !self.info_to_stderr;
+        let colored = !color.is_empty() && if to_stdout { self.color_stdout } else { self.color_stderr };
+        let line = if colored {
+            format!("{}{}\x1b[0m{}", color, prefix, record.args())
+        } else {
+            format!("{}{}", prefix, record.args())
+        };
+        if to_stdout {
+            println!("{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+
+    fn write_file(&self, record: &Record) {
+        let Ok(mut file) = self.file.lock() else { return };
+        let file = file.get_or_insert_with(|| self.open_file());
+        if let Ok(file) = file {
+            let _ = writeln!(file, "{} {:<5} {}",
+                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"), record.level(), record.args());
+        }
+    }
+
+    /// Create a new log file, removing old ones.
+    fn open_file(&self) -> std::result::Result<std::fs::File, ()> {
+        std::fs::create_dir_all(&self.log_dir).map_err(|_| ())?;
+        let mut logs: Vec<PathBuf> = std::fs::read_dir(&self.log_dir).map_err(|_| ())?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect();
+        logs.sort();
+        if logs.len() >= LOG_FILES_KEPT {
+            for path in &logs[..=(logs.len() - LOG_FILES_KEPT)] {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+
+        let name = format!("multimoon-{}-{}.log", chrono::Local::now().format("%Y%m%d-%H%M%S"), std::process::id());
+        let mut file = std::fs::File::create(self.log_dir.join(name)).map_err(|_| ())?;
+        let _ = writeln!(file, "MultiMoon {}: {}", crate::registry::MULTIMOON_VERSION,
+            std::env::args().collect::<Vec<_>>().join(" "));
+        Ok(file)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.is_own(metadata) && metadata.level() <= std::cmp::max(self.console_level, LevelFilter::Debug)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.is_own(record.metadata()) {
+            return;
+        }
+        if record.level() <= self.console_level {
+            self.write_console(record);
+        }
+        self.write_file(record);
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(Ok(file)) = file.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
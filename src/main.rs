@@ -1,89 +1,112 @@
 mod cmdline;
-mod common;
-mod core;
-mod global;
-mod installer;
-mod prelude;
-mod registry;
 mod subcommand;
 
-use crate::{global::GlobalInfo, prelude::*};
+use multimoon::{common, component, context, core, error, fetch, global, installer, logging, plan, prelude, registry, report, shell};
 
-fn init(args: &cmdline::Args) {
-    // home
-    let home = dirs::home_dir().expect("unable to detect user's home directory");
+use anyhow::Context as _;
 
-    // moonhome
-    let moonhome = args.moonhome.as_ref().map_or_else(|| {
-        home.join(".moon")
-    }, |value| {
-        value.clone()
-    });
+use crate::prelude::*;
 
-    // multimoonhome
-    let multimoonhome = args.multimoonhome.as_ref().map_or_else(|| {
-        home.join(".multimoon")
-    }, |value| {
-        value.clone()
-    });
+/// Build the context of current run from command line arguments, and set up logging.
+fn init(args: &cmdline::Args) -> Result<Context> {
+    let mut ctx = located_context(args.registry.as_deref(), args.moonhome.as_deref(), args.multimoonhome.as_deref())?;
+    ctx.modify_path = !args.no_modify_path;
+    ctx.format = args.format.into();
+    ctx.dry_run = args.dry_run;
+    ctx.yes = args.yes;
+    ctx.auto_backups = args.auto_backups;
 
-    // registry
-    let registry_str = args.registry.as_ref().map_or_else(|| {
-        "https://multimoon.lopt.dev/".to_string()
-    }, |value| {
-        value.clone()
-    });
-    let registry = Url::parse(&registry_str).expect("invalid registry url");
+    // `registry validate` always prints its report in JSON, so progress goes to stderr like in JSON output format
+    let format = match &args.command {
+        cmdline::Command::Registry(cmdline::RegistryArgs { command: cmdline::RegistryCommand::Validate(_) }) => context::OutputFormat::Json,
+        _ => ctx.format,
+    };
+    // dry runs don't touch disk, not even the log file
+    logging::init(args.verbose, args.quiet, format, (!ctx.dry_run).then_some(ctx.multimoonhome.as_path()));
+    Ok(ctx)
+}
+
+/// Default context, with the registry and directories given by `--registry`, `--moonhome` and `--multimoonhome`.
+fn located_context(registry: Option<&str>, moonhome: Option<&Path>, multimoonhome: Option<&Path>) -> Result<Context> {
+    let mut ctx = Context::new()?;
+    if let Some(value) = registry {
+        ctx.registry = fetch::parse_location(value)
+            .with_context(|| format!("invalid registry location {}", value))?;
+    }
+    if let Some(moonhome) = moonhome {
+        ctx.moonhome = moonhome.to_path_buf();
+    }
+    if let Some(multimoonhome) = multimoonhome {
+        ctx.multimoonhome = multimoonhome.to_path_buf();
+    }
+    Ok(ctx)
+}
+
+fn main() {
+    // answer shell completion requests (`COMPLETE=<shell> multimoon ...`) before anything else
+    clap_complete::CompleteEnv::with_factory(<cmdline::Args as clap::CommandFactory>::command).complete();
 
-    global::init(move || {
-        GlobalInfo {
-            home,
-            multimoonhome,
-            moonhome,
-            registry,
-            verbose: args.verbose,
-        }
-    }).unwrap()
+    // log the error (also to the log file of this run), with an exit code telling the kind of error
+    if let Err(err) = run() {
+        // no-op if logging is set up already; errors before that (e.g. invalid registry location) go to console only
+        logging::init(0, false, context::OutputFormat::Text, None);
+        error!("{:?}", err);
+        log::logger().flush();
+        std::process::exit(error::exit_code(&err));
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn run() -> Result<()> {
     use clap::Parser;
-    use subcommand::{core, toolchain};
+    use subcommand::{completions, component, core, env, registry, self_install, toolchain, uninstall, update_self};
     let args = cmdline::Args::parse();
 
-    init(&args);
+    let ctx = init(&args)?;
 
     match &args.command {
-        cmdline::Command::Show => toolchain::show().await,
-        cmdline::Command::Update => toolchain::update_to_latest().await,
+        cmdline::Command::Show => toolchain::show(&ctx).await,
+        cmdline::Command::Update => toolchain::update_to_latest(&ctx).await,
+        cmdline::Command::Env(a) => env::env(&ctx, a).await,
+        cmdline::Command::Completions(a) => completions::completions(a).await,
         cmdline::Command::Toolchain(args) => {
             match &args.command {
-                cmdline::ToolchainCommand::Show => toolchain::show().await,
-                cmdline::ToolchainCommand::List => toolchain::list().await,
-                cmdline::ToolchainCommand::Update(a) => toolchain::update(a).await,
-                cmdline::ToolchainCommand::Rollback(a) => toolchain::update(a).await,
+                cmdline::ToolchainCommand::Show => toolchain::show(&ctx).await,
+                cmdline::ToolchainCommand::List => toolchain::list(&ctx).await,
+                cmdline::ToolchainCommand::Update(a) => toolchain::update(&ctx, a).await,
+                cmdline::ToolchainCommand::Rollback(a) => toolchain::update(&ctx, a).await,
             }
         },
         cmdline::Command::Core(args) => {
             match &args.command {
-                cmdline::CoreCommand::List => core::list().await,
-                cmdline::CoreCommand::Backup(a) => core::backup(a).await,
-                cmdline::CoreCommand::Restore(a) => core::restore(a).await,
+                cmdline::CoreCommand::List(a) => core::list(&ctx, a).await,
+                cmdline::CoreCommand::Show(a) => core::show(&ctx, a).await,
+                cmdline::CoreCommand::Backup(a) => core::backup(&ctx, a).await,
+                cmdline::CoreCommand::Restore(a) => core::restore(&ctx, a).await,
             }
         },
-        cmdline::Command::UpdateSelf => update_self().await,
-    }.map_err(|e| {
-        // println!("Error backtrace: {:?}", e.backtrace());
-        e
-    })
-}
-
-async fn update_self() -> Result<()> {
-    println!("The update-self command is not implemented yet, sorry for the inconvinence! ");
-    println!("");
-    println!("Latest version of MultiMoon can be downloaded at:");
-    println!("");
-    println!("  https://github.com/lone-outpost-oss/multimoon/releases");
-    return Ok(());
+        cmdline::Command::Component(args) => {
+            match &args.command {
+                cmdline::ComponentCommand::List => component::list(&ctx).await,
+                cmdline::ComponentCommand::Add(a) => component::add(&ctx, a).await,
+                cmdline::ComponentCommand::Remove(a) => component::remove(&ctx, a).await,
+            }
+        },
+        cmdline::Command::Installers => toolchain::installers().await,
+        cmdline::Command::Registry(args) => {
+            match &args.command {
+                cmdline::RegistryCommand::Mirror(a) => registry::mirror(&ctx, a).await,
+                cmdline::RegistryCommand::Build(a) => registry::build(&ctx, a).await,
+                cmdline::RegistryCommand::Validate(a) => registry::validate(a).await,
+            }
+        },
+        cmdline::Command::Uninstall(a) => uninstall::uninstall(&ctx, a).await,
+        cmdline::Command::UpdateSelf(a) => update_self::update_self(&ctx, a).await,
+        cmdline::Command::SelfManage(args) => {
+            match &args.command {
+                cmdline::SelfCommand::Install(a) => self_install::install(&ctx, a).await,
+                cmdline::SelfCommand::Uninstall => self_install::uninstall(&ctx).await,
+            }
+        },
+    }
 }
@@ -0,0 +1,114 @@
+//! Plans of state-changing operations.
+//!
+//! With `--dry-run`, operations compute a [`Plan`] of what they would do instead of doing it. Computing a plan may
+//! read local files and query the registry, but never changes anything on disk.
+
+use serde::Serialize;
+
+use crate::prelude::*;
+
+/// Everything an operation would change.
+#[derive(Serialize, Default, Debug)]
+pub struct Plan {
+    /// Registry entry the operation was resolved to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+    /// Files to download.
+    pub downloads: Vec<Download>,
+    /// Files created or replaced.
+    pub writes: Vec<PathBuf>,
+    /// Files or directories deleted.
+    pub removes: Vec<PathBuf>,
+    /// Archives extracted.
+    pub extracts: Vec<Extract>,
+    /// Shell config files (or other locations of PATH) edited.
+    pub shell_configs: Vec<String>,
+    /// Commands run.
+    pub commands: Vec<Command>,
+}
+
+/// A file to download.
+#[derive(Serialize, Debug)]
+pub struct Download {
+    pub url: String,
+    /// Size in bytes, if the server tells.
+    pub size: Option<u64>,
+}
+
+/// An archive to extract.
+#[derive(Serialize, Debug)]
+pub struct Extract {
+    pub archive: String,
+    pub to: PathBuf,
+    /// Number of files in the archive, if known without downloading it.
+    pub files: Option<usize>,
+}
+
+/// A command to run.
+#[derive(Serialize, Debug)]
+pub struct Command {
+    pub command: String,
+    pub cwd: PathBuf,
+}
+
+impl Plan {
+    /// Check if nothing would be changed.
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty() && self.writes.is_empty() && self.removes.is_empty() && self.extracts.is_empty()
+            && self.shell_configs.is_empty() && self.commands.is_empty()
+    }
+
+    /// Append steps of `other` to this plan.
+    pub fn extend(&mut self, other: Plan) {
+        if self.resolved.is_none() {
+            self.resolved = other.resolved;
+        }
+        self.downloads.extend(other.downloads);
+        self.writes.extend(other.writes);
+        self.removes.extend(other.removes);
+        self.extracts.extend(other.extracts);
+        self.shell_configs.extend(other.shell_configs);
+        self.commands.extend(other.commands);
+    }
+}
+
+/// Paths in `paths` that exist, i.e. would actually be removed.
+pub fn existing(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    paths.into_iter().filter(|path| std::fs::symlink_metadata(path).is_ok()).collect()
+}
+
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(resolved) = &self.resolved {
+            writeln!(f, "resolved {}", resolved)?;
+        }
+        for download in &self.downloads {
+            match download.size {
+                Some(size) => writeln!(f, "download {} ({} bytes)", download.url, size)?,
+                None => writeln!(f, "download {} (size unknown)", download.url)?,
+            }
+        }
+        for path in &self.removes {
+            writeln!(f, "remove {}", path.display())?;
+        }
+        for path in &self.writes {
+            writeln!(f, "write {}", path.display())?;
+        }
+        for extract in &self.extracts {
+            match extract.files {
+                Some(files) => writeln!(f, "extract {} ({} files) to {}", extract.archive, files, extract.to.display())?,
+                None => writeln!(f, "extract {} to {}", extract.archive, extract.to.display())?,
+            }
+        }
+        for command in &self.commands {
+            writeln!(f, "run {} in {}", command.command, command.cwd.display())?;
+        }
+        for location in &self.shell_configs {
+            writeln!(f, "edit PATH in {}", location)?;
+        }
+        if self.is_empty() {
+            writeln!(f, "nothing to change")?;
+        }
+        Ok(())
+    }
+}
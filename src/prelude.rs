@@ -1,8 +1,10 @@
 //! The prelude of this project.
 
-pub use crate::global::{arch, global};
+pub use crate::context::Context;
+pub use crate::global::arch;
 pub use std::path::{Path, PathBuf};
 pub use std::sync::Arc;
 pub use std::sync::atomic::{AtomicI32, Ordering::SeqCst};
 pub use anyhow::{anyhow, Result};
+pub use log::{debug, error, info, warn};
 pub use url::Url;
@@ -1,14 +1,27 @@
 //! Interacting with MultiMoon registries.
 
-use crate::prelude::*;
+use crate::{error::Error, prelude::*};
+use anyhow::Context as _;
 use serde::{Serialize, Deserialize};
 
+/// Latest registry schema version supported by this version of MultiMoon.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// NOTE: unknown fields are ignored on purpose, so registries can add fields without breaking older clients.
+// Incompatible changes must bump `schema_version` or `min_multimoon_version` instead.
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Registry
 {
+    /// Schema version of the index, indexes without it are version 1.
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
     pub toolchains: Vec<Toolchain>,
     pub last_modified: i64,
     pub downloadfrom: String,
+    /// Latest release of MultiMoon itself for this platform, used by `update-self`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multimoon: Option<Release>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -20,6 +33,48 @@ pub struct Toolchain
     pub bin: Vec<File>,
     pub core: Vec<File>,
     pub installer: String,
+    /// Minimum version of MultiMoon to install this toolchain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_multimoon_version: Option<String>,
+    /// Installation steps, used by the `declarative` installer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install: Option<InstallSpec>,
+    /// Components which binaries are grouped into.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<Component>,
+}
+
+impl Toolchain {
+    /// Check if current version of MultiMoon is new enough for this toolchain.
+    pub fn is_supported(&self) -> bool {
+        self.min_multimoon_version.as_ref()
+            .is_none_or(|min| crate::common::compare_versions(MULTIMOON_VERSION, min).is_ge())
+    }
+
+    /// Return an error telling the user to update MultiMoon if this toolchain is not supported.
+    pub fn check_supported(&self) -> Result<()> {
+        if self.is_supported() {
+            return Ok(());
+        }
+        Err(Error::Registry(format!("registry error: toolchain {} requires MultiMoon {} or newer (current version: {}), please update MultiMoon first",
+            &self.name, self.min_multimoon_version.as_deref().unwrap_or_default(), MULTIMOON_VERSION)).into())
+    }
+}
+
+/// A release of MultiMoon, with its xz compressed executable stored in `multimoon/<version>/<arch>/`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Release
+{
+    pub version: String,
+    pub last_modified: i64,
+    pub bin: File,
+}
+
+impl Release {
+    /// Directory of the executable relative to registry `downloadfrom`.
+    pub fn dir(&self, arch: &str) -> String {
+        format!("multimoon/{}/{}/", self.version, arch)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -28,17 +83,319 @@ pub struct File
     pub filename: String,
     pub downloadfrom: String,
     pub checksum: String,
+    /// Component of this file, files without a component are always installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Component
+{
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Optional components can be removed by `component remove`.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// Installation steps of a toolchain. All paths are relative to the MoonBit home directory.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstallSpec
+{
+    /// Directories to remove before installing.
+    #[serde(default)]
+    pub wipe: Vec<String>,
+    /// Directory to install binaries to.
+    #[serde(default = "default_bin_dir")]
+    pub bin_dir: String,
+    /// Unix permissions of installed binaries, in octal.
+    #[serde(default = "default_bin_mode")]
+    pub bin_mode: String,
+    /// Per-file overrides of `bin_dir` and `bin_mode`.
+    #[serde(default)]
+    pub files: Vec<FileSpec>,
+    /// Directory to extract core archives to.
+    #[serde(default = "default_lib_dir")]
+    pub lib_dir: String,
+    /// Commands to run after all files are installed.
+    #[serde(default)]
+    pub post_install: Vec<CommandSpec>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileSpec
+{
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CommandSpec
+{
+    /// Program to run, a bare name is looked up in `bin_dir`.
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Working directory of the command.
+    #[serde(default)]
+    pub cwd: String,
+    /// Continue installing if this command fails.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+fn default_bin_dir() -> String {
+    "bin".to_string()
+}
+
+fn default_bin_mode() -> String {
+    "755".to_string()
+}
+
+fn default_lib_dir() -> String {
+    "lib".to_string()
+}
+
+/// Version of this MultiMoon.
+pub const MULTIMOON_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Index file name of a registry stored on disk or served statically.
+pub const INDEX_FILENAME: &str = "index.json";
+
+/// File name of the cached registry index in MultiMoon home directory.
+pub const CACHE_FILENAME: &str = "registry-cache.json";
+
+/// Get the registry index of current platform from the registry of `ctx`.
+pub async fn get(ctx: &Context) -> Result<Registry> {
+    let registry = get_arch(&ctx.registry, arch()).await?;
+
+    // keep a copy for offline uses such as shell completion, failing to write it is not an error
+    if ctx.dry_run {
+        return Ok(registry);
+    }
+    if let Err(err) = save_cache(ctx, &registry) {
+        debug!("failed to cache registry index: {:#}", err);
+    }
+
+    Ok(registry)
 }
 
-pub async fn get() -> Result<Registry> {
-    let url = global().registry.join(&format!("{}/", arch()))?;
+/// Path of the cached copy of the last fetched registry index.
+pub fn cache_path(ctx: &Context) -> PathBuf {
+    ctx.multimoonhome.join(CACHE_FILENAME)
+}
 
-    println!("downloading registry index from {}", &url);
-    let response = reqwest::get(url).await?.error_for_status()?;
-    let bytes = response.bytes().await?;
-    let cursor = std::io::Cursor::new(bytes);
+/// Load the cached registry index saved by [`get`].
+pub fn load_cache(ctx: &Context) -> Result<Registry> {
+    let path = cache_path(ctx);
+    let content = std::fs::read(&path)
+        .with_context(|| format!("failed to read cached registry index {}", path.display()))?;
+    parse(&content)
+}
+
+fn save_cache(ctx: &Context, registry: &Registry) -> Result<()> {
+    std::fs::create_dir_all(&ctx.multimoonhome)?;
+    crate::common::write_file_atomic(&cache_path(ctx), &serde_json::to_vec_pretty(registry)?)
+}
 
-    let registry = serde_json::from_reader::<_, Registry>(cursor)?;
+/// Get the registry index of platform `arch` from registry at `registry_url`.
+pub async fn get_arch(registry_url: &Url, arch: &str) -> Result<Registry> {
+    let client = reqwest::Client::new();
+    let url = registry_url.join(&format!("{}/", arch))?;
+
+    // a registry on disk stores its index as a plain file inside the arch directory
+    let url = match url.scheme() {
+        "file" => url.join(INDEX_FILENAME)?,
+        _ => url,
+    };
+
+    info!("downloading registry index from {}", &url);
+    let (url, mut registry) = match fetch_index(&client, &url).await {
+        Ok(registry) => (url, registry),
+        // statically served registries (e.g. mirrors) may not have a directory index, or serve a directory listing
+        // instead, then the index file decides
+        Err(err) if url.path().ends_with('/') => {
+            let index_url = url.join(INDEX_FILENAME)?;
+            match crate::fetch::fetch(&client, &index_url).await {
+                Ok(bytes) => {
+                    let registry = parse(&bytes)
+                        .with_context(|| format!("registry error: failed to load registry index {}", &index_url))?;
+                    (index_url, registry)
+                },
+                Err(_) => return Err(err),
+            }
+        },
+        Err(err) => return Err(err),
+    };
+
+    // `downloadfrom` may be relative to the index, or a plain directory path
+    registry.downloadfrom = crate::fetch::resolve_location(&url, &registry.downloadfrom)?.to_string();
 
     Ok(registry)
 }
+
+/// Fetch and parse the registry index at `url`.
+async fn fetch_index(client: &reqwest::Client, url: &Url) -> Result<Registry> {
+    let bytes = crate::fetch::fetch(client, url).await?;
+    parse(&bytes).with_context(|| format!("registry error: failed to load registry index {}", url))
+}
+
+/// Parse a registry index, checking schema version before the content so incompatible registries produce a clear
+/// message rather than a deserialization error.
+pub fn parse(content: &[u8]) -> Result<Registry> {
+    let value = serde_json::from_slice::<serde_json::Value>(content)
+        .map_err(|err| Error::Registry(format!("registry error: invalid registry index: {}", err)))?;
+    let schema_version = match value.get("schema_version") {
+        Some(v) => v.as_u64().context(Error::Registry("registry error: invalid schema_version".to_string()))?,
+        None => default_schema_version() as u64,
+    };
+    if schema_version > SCHEMA_VERSION as u64 {
+        return Err(Error::Registry(format!("registry schema version {} is not supported by MultiMoon {} (supports up to {}), please update MultiMoon first",
+            schema_version, MULTIMOON_VERSION, SCHEMA_VERSION)).into());
+    }
+    let registry = serde_json::from_value::<Registry>(value)
+        .map_err(|err| Error::Registry(format!("registry error: invalid registry index: {}", err)))?;
+    Ok(registry)
+}
+
+fn default_schema_version() -> u32 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(schema_version: &str) -> String {
+        format!(r#"{{{}"toolchains": [{{"name": "t1", "moonver": "0.1.0", "last_modified": 1, "bin": [], "core": [], "installer": "zip"}}],
+            "last_modified": 1, "downloadfrom": "https://example.com/"}}"#, schema_version)
+    }
+
+    fn is_registry_error(result: Result<Registry>) -> bool {
+        matches!(result.unwrap_err().downcast_ref::<Error>(), Some(Error::Registry(_)))
+    }
+
+    #[test]
+    fn parse_minimal_index() {
+        let registry = parse(index("").as_bytes()).unwrap();
+        assert_eq!(registry.schema_version, 1);
+        assert_eq!(registry.toolchains.len(), 1);
+        assert_eq!(registry.toolchains[0].name, "t1");
+        assert!(registry.multimoon.is_none());
+    }
+
+    #[test]
+    fn parse_current_schema_version() {
+        let registry = parse(index(&format!(r#""schema_version": {}, "#, SCHEMA_VERSION)).as_bytes()).unwrap();
+        assert_eq!(registry.schema_version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn parse_rejects_newer_schema_version() {
+        assert!(is_registry_error(parse(index(&format!(r#""schema_version": {}, "#, SCHEMA_VERSION + 1)).as_bytes())));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_schema_version() {
+        for value in [r#""2""#, "-1", "1.5", "null"] {
+            assert!(is_registry_error(parse(index(&format!(r#""schema_version": {}, "#, value)).as_bytes())), "schema_version {}", value);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_invalid_index() {
+        assert!(is_registry_error(parse(b"not json")));
+        assert!(is_registry_error(parse(br#"{"toolchains": []}"#)));
+    }
+
+    #[test]
+    fn parse_ignores_unknown_fields() {
+        let registry = parse(index(r#""unknown": {"nested": true}, "#).as_bytes()).unwrap();
+        assert_eq!(registry.toolchains.len(), 1);
+    }
+
+    #[test]
+    fn toolchain_min_multimoon_version() {
+        let mut toolchain = parse(index("").as_bytes()).unwrap().toolchains.remove(0);
+        assert!(toolchain.is_supported());
+        toolchain.min_multimoon_version = Some(MULTIMOON_VERSION.to_string());
+        assert!(toolchain.check_supported().is_ok());
+        toolchain.min_multimoon_version = Some("999.0.0".to_string());
+        assert!(!toolchain.is_supported());
+        assert!(matches!(toolchain.check_supported().unwrap_err().downcast_ref::<Error>(), Some(Error::Registry(_))));
+    }
+
+    /// Serve files in `root` over HTTP like a plain static server, answering directories with an HTML listing.
+    async fn serve(root: PathBuf) -> Url {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let root = root.clone();
+                tokio::spawn(async move {
+                    let mut request = vec![];
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = root.join(request.split(' ').nth(1).unwrap().trim_start_matches('/'));
+                    let (status, body) = if path.is_dir() {
+                        ("200 OK", b"<!DOCTYPE HTML><html><body><a href=\"index.json\">index.json</a></body></html>".to_vec())
+                    } else if path.is_file() {
+                        ("200 OK", std::fs::read(&path).unwrap())
+                    } else {
+                        ("404 Not Found", vec![])
+                    };
+                    let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    stream.write_all(&body).await.unwrap();
+                });
+            }
+        });
+        url
+    }
+
+    fn write_index(root: &Path, content: &str) {
+        std::fs::create_dir_all(root.join("ubuntu_amd64")).unwrap();
+        std::fs::write(root.join("ubuntu_amd64").join(INDEX_FILENAME), content).unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_statically_served_mirror() {
+        let root = tempdir::TempDir::new("multimoon-registry").unwrap();
+        write_index(root.path(), &index("").replace("https://example.com/", "../"));
+        let url = serve(root.path().to_path_buf()).await;
+
+        let registry = get_arch(&url, "ubuntu_amd64").await.unwrap();
+        assert_eq!(registry.toolchains[0].name, "t1");
+        assert_eq!(registry.downloadfrom, url.as_str());
+    }
+
+    #[tokio::test]
+    async fn get_invalid_index_reports_parse_error() {
+        let root = tempdir::TempDir::new("multimoon-registry").unwrap();
+        write_index(root.path(), "{ not json");
+        let url = serve(root.path().to_path_buf()).await;
+
+        let err = get_arch(&url, "ubuntu_amd64").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Registry(_))));
+        assert!(format!("{:#}", err).contains("key must be a string"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn get_missing_index() {
+        let root = tempdir::TempDir::new("multimoon-registry").unwrap();
+        let url = serve(root.path().to_path_buf()).await;
+        assert!(get_arch(&url, "ubuntu_amd64").await.is_err());
+    }
+}
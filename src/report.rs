@@ -0,0 +1,120 @@
+//! Reporting progress of installation and core library operations.
+//!
+//! Long running operations emit [`Event`]s to a [`Reporter`] instead of printing directly, so MultiMoon can be
+//! driven by other frontends such as IDE plugins. [`CliReporter`] prints events for the command line.
+
+use std::time::Duration;
+
+use crate::prelude::*;
+
+/// Kind of a downloaded or installed file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    /// A binary of the toolchain.
+    Bin,
+    /// The core library archive.
+    Lib,
+}
+
+impl std::fmt::Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FileKind::Bin => "bin",
+            FileKind::Lib => "lib",
+        })
+    }
+}
+
+/// Progress of an operation. Indexes are 1-based, counts of files are 0-based.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    DownloadStarted { kind: FileKind, index: usize, total: usize, url: &'a Url },
+    /// Bytes downloaded so far, and total size if known.
+    DownloadProgress { url: &'a Url, downloaded: u64, size: Option<u64> },
+    DownloadFinished { kind: FileKind, index: usize, total: usize, filename: &'a str, size: u64, duration: Duration },
+    FileInstalled { kind: FileKind, index: usize, total: usize, path: &'a Path },
+    FileRemoved { path: &'a Path },
+    ArchiveStarted { path: &'a Path },
+    FileArchived { count: usize, path: &'a Path },
+    ExtractStarted { lib_path: &'a Path },
+    FileExtracted { count: usize, path: &'a Path },
+    ExtractFinished { lib_path: &'a Path, files: usize },
+    BundleStarted { core_path: &'a Path },
+    BundleFinished { core_path: &'a Path },
+    /// An external command is run, e.g. `moon bundle` or post-install commands.
+    CommandStarted { description: &'a str, command: &'a str, cwd: &'a str },
+    CommandFinished { program: &'a str, success: bool, stdout: &'a str, stderr: &'a str },
+    /// Any other progress message.
+    Status(&'a str),
+    Warning(&'a str),
+}
+
+/// Receiver of [`Event`]s, may be called from multiple tasks concurrently.
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: &Event);
+}
+
+/// Number of archived or extracted files shown without verbose output.
+const FILES_SHOWN: usize = 5;
+
+/// Prints events as log messages of the command line.
+pub struct CliReporter;
+
+impl Reporter for CliReporter {
+    fn report(&self, event: &Event) {
+        match event {
+            Event::DownloadStarted { kind, index, total, url } => {
+                info!("downloading [{} {} / {}] {} ...", kind, index, total, url);
+            },
+            Event::DownloadProgress { url, downloaded, size } => {
+                log::trace!("downloading {}: {} / {} bytes", url, downloaded,
+                    size.map(|s| s.to_string()).unwrap_or_else(|| "?".to_string()));
+            },
+            Event::DownloadFinished { kind, index, total, filename, size, duration } => {
+                info!("downloaded [{} {} / {}] {} ({:.2} KiB/s) ...", kind, index, total, filename,
+                    (*size as f64) / duration.as_secs_f64() / 1024f64);
+            },
+            Event::FileInstalled { kind, index, total, path } => {
+                info!("installed [{} {} / {}] {}", kind, index, total, path.display());
+            },
+            Event::FileRemoved { path } => info!("removed {}", path.display()),
+            Event::ArchiveStarted { path } => info!("archiving {}", path.display()),
+            Event::FileArchived { count, path } => show_file("archiving", "archived", *count, path),
+            Event::ExtractStarted { lib_path } => debug!("extracting core to {}", lib_path.display()),
+            Event::FileExtracted { count, path } => show_file("extract to", "extracted", *count, path),
+            Event::ExtractFinished { lib_path, files } => debug!("extracted {} files to {}", files, lib_path.display()),
+            Event::BundleStarted { core_path } => debug!("bundling core library {}", core_path.display()),
+            Event::BundleFinished { core_path } => info!("succesfully bundled core library {}.", core_path.display()),
+            Event::CommandStarted { description, command, cwd } => info!("{} (run {} in {})", description, command, cwd),
+            Event::CommandFinished { program, success, stdout, stderr } => {
+                // output is kept in the log file, and shown on failure or in verbose mode
+                let level = if *success { log::Level::Debug } else { log::Level::Error };
+                for (name, content) in [("stdout", stdout), ("stderr", stderr)] {
+                    if !content.trim().is_empty() {
+                        log::log!(level, "{} of {}:\n{}", name, program, content.trim_end());
+                    }
+                }
+            },
+            Event::Status(message) => info!("{}", message),
+            Event::Warning(message) => warn!("{}", message),
+        }
+    }
+}
+
+/// Show the first few files, leaving the rest to verbose output.
+fn show_file(action: &str, omitted: &str, count: usize, path: &Path) {
+    if count < FILES_SHOWN {
+        info!("{} {}", action, path.display());
+    } else {
+        if count == FILES_SHOWN && !crate::logging::console_enabled(log::Level::Debug) {
+            info!(" (further {} files omitted)", omitted);
+        }
+        debug!("{} {}", action, path.display());
+    }
+}
+
+/// The reporter of the command line.
+pub fn cli() -> Arc<dyn Reporter> {
+    Arc::new(CliReporter)
+}
@@ -0,0 +1,366 @@
+//! Configuring PATH of user shells.
+//!
+//! On unix, PATH is configured by a block delimited by begin and end markers in shell config files, so it can be
+//! updated or removed later without touching anything else in the file. On Windows, PATH of current user is
+//! configured in registry.
+
+use anyhow::Context as _;
+
+use crate::prelude::*;
+
+/// Shells supported by MultiMoon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Shell {
+    Sh,
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+    PowerShell,
+    Tcsh,
+}
+
+impl Shell {
+    pub const ALL: &[Shell] = &[Shell::Sh, Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Nushell, Shell::PowerShell, Shell::Tcsh];
+
+    /// Detect shell of current user from `SHELL` environment variable.
+    pub fn detect() -> Result<Shell> {
+        const ERR_DETECT: &str = "cannot detect current shell";
+        let shell_path = PathBuf::from(std::env::var("SHELL").context(ERR_DETECT)?);
+        let shell_filename = shell_path.file_stem().context(ERR_DETECT)?.to_str().context(ERR_DETECT)?;
+        Ok(match shell_filename {
+            "bash" => Shell::Bash,
+            "zsh" => Shell::Zsh,
+            "fish" => Shell::Fish,
+            "nu" => Shell::Nushell,
+            "pwsh" | "powershell" => Shell::PowerShell,
+            "tcsh" | "csh" => Shell::Tcsh,
+            _ => Shell::Sh,
+        })
+    }
+
+    /// Config file of this shell, where PATH is configured.
+    pub fn config_path(&self, ctx: &Context) -> PathBuf {
+        let home = ctx.home.as_path();
+        match self {
+            Shell::Sh => home.join(".profile"),
+            Shell::Bash => home.join(".bashrc"),
+            Shell::Zsh => std::env::var_os("ZDOTDIR").map(PathBuf::from).unwrap_or_else(|| home.to_path_buf()).join(".zshrc"),
+            Shell::Fish => home.join(".config").join("fish").join("config.fish"),
+            Shell::Nushell => home.join(".config").join("nushell").join("env.nu"),
+            Shell::PowerShell => home.join(".config").join("powershell").join("Microsoft.PowerShell_profile.ps1"),
+            Shell::Tcsh => home.join(".tcshrc"),
+        }
+    }
+
+    /// A statement of this shell prepending `path` to PATH.
+    pub fn prepend_path(&self, path: &str) -> String {
+        match self {
+            Shell::Sh | Shell::Bash | Shell::Zsh => format!("export PATH=\"{}:$PATH\"", path),
+            Shell::Fish => format!("set -gx PATH \"{}\" $PATH", path),
+            Shell::Nushell => format!("$env.PATH = ($env.PATH | split row (char esep) | prepend '{}')", path),
+            Shell::PowerShell => format!("$env:PATH = \"{}\" + [IO.Path]::PathSeparator + $env:PATH", path),
+            Shell::Tcsh => format!("setenv PATH \"{}:$PATH\"", path),
+        }
+    }
+
+    /// A statement of this shell setting environment variable `name` to `value`.
+    pub fn set_var(&self, name: &str, value: &str) -> String {
+        match self {
+            Shell::Sh | Shell::Bash | Shell::Zsh => format!("export {}=\"{}\"", name, value),
+            Shell::Fish => format!("set -gx {} \"{}\"", name, value),
+            Shell::Nushell => format!("$env.{} = '{}'", name, value),
+            Shell::PowerShell => format!("$env:{} = \"{}\"", name, value),
+            Shell::Tcsh => format!("setenv {} \"{}\"", name, value),
+        }
+    }
+}
+
+/// Add `path` to the PATH of current user, in shell config file on unix and in registry on Windows. Returns
+/// descriptions of changed locations, nothing is changed if `dry_run`.
+pub fn add_path_to_shell<P: AsRef<std::path::Path>>(ctx: &Context, path: P, dry_run: bool) -> Result<Vec<String>> {
+    #[cfg(windows)]
+    {
+        use winreg::{enums::*, RegKey};
+        let _ = ctx;
+        let path_str = path.as_ref().to_str().context("unsupported path name")?;
+
+        const ERR_READ: &str = "cannot read registry";
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let env = hkcu.open_subkey_with_flags("Environment", KEY_QUERY_VALUE | KEY_SET_VALUE)
+            .context(ERR_READ)?;
+        let path = env.get_value::<String, _>("Path").context(ERR_READ)?;
+
+        if path.split(';').any(|entry| entry == path_str) {
+            if !dry_run {
+                info!("{} has already been configured in user PATH environment variable.", path_str);
+            }
+            return Ok(vec![]);
+        }
+        if !dry_run {
+            info!("adding {} to user PATH environment variable", path_str);
+            const ERR_WRITE: &str = "cannot write registry";
+            let path_new = format!("{};{}", path_str, &path);
+            env.set_value("Path", &path_new).context(ERR_WRITE)?;
+        }
+
+        Ok(vec!["user PATH environment variable".to_string()])
+    }
+
+    #[cfg(unix)]
+    {
+        let path_str = path.as_ref().to_str().context("unsupported path name")?;
+        let shell = Shell::detect()?;
+        let shell_config_path = shell.config_path(ctx);
+
+        // missing config files are created
+        let shell_config_content = match std::fs::read_to_string(&shell_config_path) {
+            Ok(content) => Some(content),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(Into::<anyhow::Error>::into(err)
+                .context(format!("cannot read shell config file {}", shell_config_path.display()))),
+        };
+
+        let content = shell_config_content.clone().unwrap_or_default();
+        if content.contains(&managed_block(shell, path_str)) {
+            if !dry_run {
+                info!("{} has already been configured in PATH of shell config {}.", path_str, shell_config_path.display());
+            }
+            return Ok(vec![]);
+        }
+        if dry_run {
+            return Ok(vec![shell_config_path.display().to_string()]);
+        }
+
+        let content_new = add_block(&content, shell, path_str);
+
+        info!("adding {} to the PATH of current shell config: {}", path_str, shell_config_path.display());
+        if let Some(original) = &shell_config_content {
+            backup_shell_config(&shell_config_path, original)?;
+        } else if let Some(parent) = shell_config_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("cannot create directory {}", parent.display()))?;
+        }
+        std::fs::write(&shell_config_path, &content_new)
+            .with_context(|| format!("cannot write shell config file {}", shell_config_path.display()))?;
+
+        Ok(vec![shell_config_path.display().to_string()])
+    }
+
+    #[cfg(not(any(windows, unix)))]
+    {
+        compile_error!("unsupported platform")
+    }
+}
+
+/// Remove `path` added by [`add_path_to_shell`] from the PATH of current user. Returns descriptions of changed
+/// locations, nothing is changed if `dry_run`.
+pub fn remove_path_from_shell<P: AsRef<std::path::Path>>(ctx: &Context, path: P, dry_run: bool) -> Result<Vec<String>> {
+    #[cfg(windows)]
+    {
+        use winreg::{enums::*, RegKey};
+        let _ = ctx;
+        let path_str = path.as_ref().to_str().context("unsupported path name")?;
+
+        const ERR_READ: &str = "cannot read registry";
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let env = hkcu.open_subkey_with_flags("Environment", KEY_QUERY_VALUE | KEY_SET_VALUE)
+            .context(ERR_READ)?;
+        let path = env.get_value::<String, _>("Path").context(ERR_READ)?;
+
+        let entries: Vec<&str> = path.split(';').collect();
+        let kept: Vec<&str> = entries.iter().copied().filter(|entry| *entry != path_str).collect();
+        if kept.len() == entries.len() {
+            return Ok(vec![]);
+        }
+        if !dry_run {
+            const ERR_WRITE: &str = "cannot write registry";
+            env.set_value("Path", &kept.join(";")).context(ERR_WRITE)?;
+        }
+        Ok(vec!["user PATH environment variable".to_string()])
+    }
+
+    #[cfg(unix)]
+    {
+        let path_str = path.as_ref().to_str().context("unsupported path name")?;
+
+        // check config files of all shells, since current shell may have changed since installation
+        let mut changed = vec![];
+        let mut shell_config_paths: Vec<PathBuf> = Shell::ALL.iter().map(|s| s.config_path(ctx)).collect();
+        shell_config_paths.dedup();
+        for shell_config_path in shell_config_paths {
+            let shell_config_content = match std::fs::read_to_string(&shell_config_path) {
+                Ok(content) => content,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(Into::<anyhow::Error>::into(err)
+                    .context(format!("cannot read shell config file {}", shell_config_path.display()))),
+            };
+
+            let (content_new, stripped) = strip_path(&shell_config_content, path_str);
+            if !stripped {
+                continue;
+            }
+            if !dry_run {
+                backup_shell_config(&shell_config_path, &shell_config_content)?;
+                std::fs::write(&shell_config_path, &content_new)
+                    .with_context(|| format!("cannot write shell config file {}", shell_config_path.display()))?;
+            }
+            changed.push(shell_config_path.display().to_string());
+        }
+        Ok(changed)
+    }
+
+    #[cfg(not(any(windows, unix)))]
+    {
+        compile_error!("unsupported platform")
+    }
+}
+
+#[cfg(unix)]
+fn begin_marker(path: &str) -> String {
+    format!("# >>> multimoon PATH {} >>>", path)
+}
+
+#[cfg(unix)]
+fn end_marker(path: &str) -> String {
+    format!("# <<< multimoon PATH {} <<<", path)
+}
+
+/// The block added to shell config files (ending with a newline).
+#[cfg(unix)]
+fn managed_block(shell: Shell, path: &str) -> String {
+    format!("{}\n# added by MultiMoon, run `multimoon uninstall` to remove\n{}\n{}\n",
+        begin_marker(path), shell.prepend_path(path), end_marker(path))
+}
+
+/// Add the block of `path` to the end of a shell config, separated by a blank line, replacing any previously added
+/// block or legacy line of the same path.
+#[cfg(unix)]
+fn add_block(content: &str, shell: Shell, path: &str) -> String {
+    let (content_stripped, _) = strip_path(content, path);
+    format!("{}{}{}",
+        content_stripped,
+        if content_stripped.is_empty() || content_stripped.ends_with("\n\n") { "" }
+            else if content_stripped.ends_with('\n') { "\n" }
+            else { "\n\n" },
+        managed_block(shell, path))
+}
+
+/// Strip all blocks of `path` from a shell config, along with lines added by MultiMoon 0.1.x (an `export` line
+/// preceded by a blank line). Returns the new content and if anything was stripped.
+#[cfg(unix)]
+fn strip_path(content: &str, path: &str) -> (String, bool) {
+    let (begin, end) = (begin_marker(path), end_marker(path));
+    let legacy_line = Shell::Sh.prepend_path(path);
+
+    let lines: Vec<&str> = content.split('\n').collect();
+    let mut removed = vec![false; lines.len()];
+    let mut in_block = false;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line == begin {
+            in_block = true;
+        }
+        if in_block || line == legacy_line {
+            removed[i] = true;
+        }
+        if line == end {
+            in_block = false;
+        }
+    }
+    // blank lines separating removed lines from previous content
+    for i in 1..lines.len() {
+        if removed[i] && !removed[i - 1] && lines[i - 1].trim().is_empty() {
+            removed[i - 1] = true;
+        }
+    }
+
+    if !removed.contains(&true) {
+        return (content.to_string(), false);
+    }
+    let content_new = lines.iter().zip(&removed)
+        .filter(|(_, removed)| !**removed)
+        .map(|(line, _)| *line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    (content_new, true)
+}
+
+/// Keep a copy of the original shell config file before the first modification by MultiMoon.
+#[cfg(unix)]
+fn backup_shell_config(shell_config_path: &Path, content: &str) -> Result<()> {
+    let mut backup_name = shell_config_path.file_name().unwrap_or_default().to_os_string();
+    backup_name.push(".multimoon-backup");
+    let backup_path = shell_config_path.with_file_name(backup_name);
+    if !backup_path.exists() {
+        info!("saving a backup of {} to {}", shell_config_path.display(), backup_path.display());
+        std::fs::write(&backup_path, content)
+            .with_context(|| format!("cannot write backup of shell config file {}", backup_path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/home/user/.moon/bin";
+
+    #[test]
+    fn add_then_remove_restores_content() {
+        for content in ["", "alias ll='ls -l'\n", "export A=1\nexport B=2\n"] {
+            let added = add_block(content, Shell::Bash, PATH);
+            assert!(added.contains(&managed_block(Shell::Bash, PATH)));
+            assert_eq!(strip_path(&added, PATH), (content.to_string(), true), "content {:?}", content);
+        }
+    }
+
+    #[test]
+    fn add_is_idempotent() {
+        let once = add_block("alias ll='ls -l'\n", Shell::Zsh, PATH);
+        let twice = add_block(&once, Shell::Zsh, PATH);
+        assert_eq!(once, twice);
+        assert_eq!(twice.matches(&begin_marker(PATH)).count(), 1);
+    }
+
+    #[test]
+    fn remove_without_block_changes_nothing() {
+        let content = "alias ll='ls -l'\n# >>> multimoon PATH /other/bin >>>\n";
+        assert_eq!(strip_path(content, PATH), (content.to_string(), false));
+    }
+
+    #[test]
+    fn remove_keeps_blocks_of_other_paths() {
+        let content = add_block(&add_block("", Shell::Bash, "/other/bin"), Shell::Bash, PATH);
+        assert_eq!(strip_path(&content, PATH).0, managed_block(Shell::Bash, "/other/bin"));
+    }
+
+    #[test]
+    fn remove_legacy_line() {
+        let content = format!("alias ll='ls -l'\n\n{}\n", Shell::Sh.prepend_path(PATH));
+        assert_eq!(strip_path(&content, PATH), ("alias ll='ls -l'\n".to_string(), true));
+    }
+
+    #[test]
+    fn crlf_config() {
+        let content = "alias ll='ls -l'\r\nexport A=1\r\n";
+        let added = add_block(content, Shell::Bash, PATH);
+        assert!(added.starts_with(content));
+        assert_eq!(add_block(&added, Shell::Bash, PATH), added);
+        assert_eq!(strip_path(&added, PATH), (content.to_string(), true));
+
+        // markers are found regardless of line endings
+        let crlf_block = managed_block(Shell::Bash, PATH).replace('\n', "\r\n");
+        assert_eq!(strip_path(&format!("{}{}", content, crlf_block), PATH), (content.to_string(), true));
+    }
+
+    #[test]
+    fn config_without_trailing_newline() {
+        let added = add_block("export A=1", Shell::Bash, PATH);
+        assert_eq!(added, format!("export A=1\n\n{}", managed_block(Shell::Bash, PATH)));
+        assert_eq!(add_block(&added, Shell::Bash, PATH), added);
+        // the last line is terminated after removal
+        assert_eq!(strip_path(&added, PATH), ("export A=1\n".to_string(), true));
+    }
+}
@@ -0,0 +1,92 @@
+//! Subcommand completions, and dynamic completion of argument values.
+//!
+//! Completion is served by MultiMoon itself: the script printed by `completions` calls back into
+//! `COMPLETE=<shell> multimoon -- <args>`, which is answered at the very start of `main`.
+
+use anyhow::Context as _;
+use clap_complete::CompletionCandidate;
+
+use crate::prelude::*;
+
+/// Environment variable requesting completions, as used by the registration scripts.
+const COMPLETE_VAR: &str = "COMPLETE";
+
+pub async fn completions(args: &crate::cmdline::CompletionsArgs) -> Result<()> {
+    let shells = clap_complete::env::Shells::builtins();
+    let shell = shells.completer(&args.shell.to_string())
+        .with_context(|| format!("unsupported shell {}", &args.shell))?;
+    let executable = std::env::current_exe().context("cannot locate MultiMoon executable")?;
+    let executable = executable.to_str().context("unsupported path name")?;
+
+    shell.write_registration(COMPLETE_VAR, "multimoon", "multimoon", executable, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+/// Names of toolchains in the cached registry index, latest first.
+pub fn toolchain_candidates() -> Vec<CompletionCandidate> {
+    let Some(ctx) = completing_context() else {
+        return vec![];
+    };
+    let Ok(registry) = crate::registry::load_cache(&ctx) else {
+        return vec![];
+    };
+    let mut toolchains = registry.toolchains;
+    toolchains.sort_by_key(|t| std::cmp::Reverse(t.last_modified));
+    toolchains.into_iter()
+        .filter(|t| t.is_supported())
+        .map(|t| CompletionCandidate::new(t.name).help(Some(format!("MoonBit {}", t.moonver).into())))
+        .collect()
+}
+
+/// Names of core backups.
+pub fn core_backup_candidates() -> Vec<CompletionCandidate> {
+    let Some(ctx) = completing_context() else {
+        return vec![];
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread().build() {
+        Ok(runtime) => runtime,
+        Err(_) => return vec![],
+    };
+    runtime.block_on(crate::core::list(&ctx)).unwrap_or_default()
+        .into_iter()
+        .map(|backup| CompletionCandidate::new(backup.name))
+        .collect()
+}
+
+/// Context of the command line being completed: the words after `--` in `COMPLETE=<shell> multimoon -- <args>`.
+/// They are parsed leniently, as the command is usually incomplete, only to honor `--registry`, `--moonhome` and
+/// `--multimoonhome`.
+fn completing_context() -> Option<Context> {
+    let words = std::env::args_os().skip_while(|arg| arg != "--").skip(1);
+    completing_context_of(words)
+}
+
+fn completing_context_of(words: impl IntoIterator<Item = std::ffi::OsString>) -> Option<Context> {
+    let matches = <crate::cmdline::Args as clap::CommandFactory>::command()
+        .ignore_errors(true)
+        .try_get_matches_from(words)
+        .ok()?;
+    crate::located_context(
+        matches.try_get_one::<String>("registry").ok().flatten().map(|s| s.as_str()),
+        matches.try_get_one::<PathBuf>("moonhome").ok().flatten().map(|p| p.as_path()),
+        matches.try_get_one::<PathBuf>("multimoonhome").ok().flatten().map(|p| p.as_path()),
+    ).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_from_incomplete_command_line() {
+        let words = ["multimoon", "--moonhome", "/tmp/moon", "--multimoonhome=/tmp/mm", "core", "restore", ""];
+        let ctx = completing_context_of(words.map(Into::into)).unwrap();
+        assert_eq!(ctx.moonhome, Path::new("/tmp/moon"));
+        assert_eq!(ctx.multimoonhome, Path::new("/tmp/mm"));
+
+        let words = ["multimoon", "--registry", "/tmp/registry", "toolchain", "update", "--bogus", ""];
+        let ctx = completing_context_of(words.map(Into::into)).unwrap();
+        assert_eq!(ctx.registry.as_str(), "file:///tmp/registry/");
+        assert_eq!(ctx.moonhome, Context::new().unwrap().moonhome);
+    }
+}
@@ -0,0 +1,140 @@
+//! Subcommands under component.
+
+use anyhow::Context as _;
+
+use crate::{component, error::Error, installer::{self, Installer}, plan::Plan, registry, prelude::*};
+
+pub async fn list(ctx: &Context) -> Result<()> {
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
+    let (_, toolchain, installed) = current_toolchain(ctx).await?;
+    let state = component::load(ctx)?;
+
+    if installed {
+        println!("components of toolchain {}:", &toolchain.name);
+    } else {
+        println!("components of toolchain {} (not installed yet, selected for the next install):", &toolchain.name);
+    }
+    if toolchain.components.is_empty() {
+        println!("  (no components, all binaries are required)");
+        return Ok(());
+    }
+    for c in &toolchain.components {
+        let selected = !(c.optional && state.removed.contains(&c.name));
+        let files: Vec<&str> = toolchain.bin.iter()
+            .filter(|f| f.component.as_deref() == Some(c.name.as_str()))
+            .map(|f| f.filename.as_str())
+            .collect();
+        println!("{} ({}{}) {}", &c.name,
+            if selected { "installed" } else { "removed" },
+            if c.optional { ", optional" } else { "" },
+            &c.description);
+        println!("  files: {}", files.join(", "));
+    }
+    Ok(())
+}
+
+pub async fn add(ctx: &Context, args: &crate::cmdline::ComponentNamesArgs) -> Result<()> {
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
+    let (registry, toolchain, installed) = current_toolchain(ctx).await?;
+    for name in &args.names {
+        if !toolchain.components.iter().any(|c| &c.name == name) {
+            return Err(Error::NotFound(format!("error: component {} not found in toolchain {}", name, &toolchain.name)).into());
+        }
+    }
+
+    // install binaries of the components (unless no toolchain is installed yet), then mark them as installed
+    let files: Vec<_> = toolchain.bin.iter()
+        .filter(|f| installed && f.component.as_ref().is_some_and(|c| args.names.contains(c)))
+        .cloned()
+        .collect();
+    let installer = installer::get_installer(&toolchain.installer)?;
+    if ctx.dry_run {
+        let mut plan = if installed { installer.plan_binaries(ctx, &registry, &toolchain, &files).await? } else { Plan::default() };
+        plan.writes.push(component::state_path(ctx));
+        return super::print_plan(ctx, &plan);
+    }
+    if installed {
+        installer.install_binaries(ctx, &registry, &toolchain, &files, &crate::report::cli()).await?;
+    }
+
+    let mut state = component::load(ctx)?;
+    state.removed.retain(|c| !args.names.contains(c));
+    component::save(ctx, &state)?;
+
+    if installed {
+        info!("added components {} to toolchain {}.", args.names.join(", "), &toolchain.name);
+    } else {
+        info!("components {} will be installed with the toolchain.", args.names.join(", "));
+    }
+    Ok(())
+}
+
+pub async fn remove(ctx: &Context, args: &crate::cmdline::ComponentNamesArgs) -> Result<()> {
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
+    let (_, toolchain, installed) = current_toolchain(ctx).await?;
+    for name in &args.names {
+        if !toolchain.components.iter().any(|c| &c.name == name) {
+            return Err(Error::NotFound(format!("error: component {} not found in toolchain {}", name, &toolchain.name)).into());
+        }
+        if !component::is_optional(&toolchain, name) {
+            return Err(Error::Usage(format!("error: component {} is required by toolchain {}", name, &toolchain.name)).into());
+        }
+    }
+
+    let installer = installer::get_installer(&toolchain.installer)?;
+    let paths = toolchain.bin.iter()
+        .filter(|f| f.component.as_ref().is_some_and(|c| args.names.contains(c)))
+        .map(|f| installer.binary_path(ctx, &toolchain, f))
+        .collect::<Result<Vec<_>>>()?;
+    if ctx.dry_run {
+        let plan = Plan {
+            writes: vec![component::state_path(ctx)],
+            removes: crate::plan::existing(paths),
+            ..Plan::default()
+        };
+        return super::print_plan(ctx, &plan);
+    }
+
+    // mark components as removed, then remove their binaries
+    let mut state = component::load(ctx)?;
+    for name in &args.names {
+        if !state.removed.contains(name) {
+            state.removed.push(name.clone());
+        }
+    }
+    component::save(ctx, &state)?;
+
+    for path in paths {
+        match std::fs::remove_file(&path) {
+            Ok(()) => info!("removed {}", path.display()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(Into::<anyhow::Error>::into(err)
+                .context(format!("failed to remove {}", path.display()))),
+        }
+    }
+
+    if installed {
+        info!("removed components {} from toolchain {}.", args.names.join(", "), &toolchain.name);
+    } else {
+        info!("components {} will be skipped when installing a toolchain.", args.names.join(", "));
+    }
+    Ok(())
+}
+
+/// The registry and the toolchain whose components are managed, and whether it's installed. Before any toolchain is
+/// installed, that's the latest toolchain, so components can be chosen before the first install.
+async fn current_toolchain(ctx: &Context) -> Result<(registry::Registry, registry::Toolchain, bool)> {
+    let registry = registry::get(ctx).await?;
+    if let Some(toolchain) = installer::current_toolchain(ctx, &registry).await? {
+        return Ok((registry, toolchain, true));
+    }
+    if ctx.moonhome.join("bin").join(crate::global::moon_executable_name()).exists() {
+        return Err(anyhow!("error: current toolchain is not listed in the registry"));
+    }
+    let toolchain = registry.toolchains.iter()
+        .filter(|t| t.is_supported())
+        .max_by_key(|t| t.last_modified)
+        .cloned()
+        .context(Error::Registry("registry error: no toolchains found".to_string()))?;
+    Ok((registry, toolchain, false))
+}
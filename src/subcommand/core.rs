@@ -1,72 +1,360 @@
 //! Subcommands under core.
 
-use crate::{core::{archive, core_backups_path, extract}, prelude::*};
+use anyhow::Context as _;
+use crate::{context::OutputFormat, core::{archive, archive_metadata, core_backups_path, find_backup, save_backup, Backup, BackupMetadata}, error::Error, plan::{self, Plan}, prelude::*};
+use crate::report::CliReporter;
 
-pub async fn list() -> Result<()> {
-    let result = crate::core::list().await?;
+pub async fn list(ctx: &Context, args: &crate::cmdline::CoreListArgs) -> Result<()> {
+    let result = crate::core::list(ctx).await?;
+    if ctx.format == OutputFormat::Json {
+        let json = serde_json::json!({ "backups": result });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
     for item in result {
-        println!("{}", item);
+        if !args.long {
+            println!("{}", item.name);
+            continue;
+        }
+        let Some(metadata) = &item.metadata else {
+            println!("{}  {}  (no metadata)", item.name, format_time(item.last_modified));
+            continue;
+        };
+        println!("{}  {}  {}  {} files, {}{}", item.name, format_time(item.last_modified), format_toolchain(metadata),
+            metadata.files, format_size(metadata.size),
+            metadata.description.as_ref().map(|d| format!("  {}", d)).unwrap_or_default());
+    }
+    Ok(())
+}
+
+pub async fn show(ctx: &Context, args: &crate::cmdline::CoreShowArgs) -> Result<()> {
+    let backup_name = strip_zip_suffix(&args.name);
+    let backups = match core_backups_path(ctx).exists() {
+        true => crate::core::list(ctx).await?,
+        false => vec![],
+    };
+    let backup = backups.into_iter()
+        .find(|backup| backup.name == backup_name)
+        .ok_or_else(|| Error::NotFound(format!("error: core backup {} not found", &backup_name)))?;
+
+    // backups of older versions have no metadata, read what is in the archive
+    let recorded = backup.metadata.is_some();
+    let metadata = match backup.metadata {
+        Some(metadata) => metadata,
+        None => archive_metadata(&mut open_backup(ctx, &backup_name)?.1)?,
+    };
+    if ctx.format == OutputFormat::Json {
+        let json = serde_json::json!({ "backup": Backup { metadata: Some(metadata), ..backup }, "recorded": recorded });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+    println!("name: {}", &backup.name);
+    println!("created: {}", format_time(backup.last_modified));
+    if recorded {
+        println!("toolchain: {}", format_toolchain(&metadata));
+    } else {
+        println!("toolchain: unknown (created by an older version of MultiMoon)");
+    }
+    if let Some(description) = &metadata.description {
+        println!("description: {}", description);
+    }
+    println!("files: {} ({})", metadata.files, format_size(metadata.size));
+    println!("digest: {}", &metadata.digest);
+    if let Some(git_commit) = &metadata.git_commit {
+        println!("git commit: {}", git_commit);
     }
     Ok(())
 }
 
-pub async fn backup(args: &crate::cmdline::CoreBackupArgs) -> Result<()> {
-    use std::io::Seek;
-    println!("MoonBit homedir: {}", global().moonhome.display());
-    use_multimoon_home().await?;
+pub async fn backup(ctx: &Context, args: &crate::cmdline::CoreBackupArgs) -> Result<()> {
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
+    use_multimoon_home(ctx).await?;
     
     // generate zip archive
-    let lib_path = global().moonhome.join("lib");
-    let archive = archive(&lib_path).await?;
-    let mut archive_reader = archive.into_inner();
-    archive_reader.seek(std::io::SeekFrom::Start(0))?;
+    let lib_path = ctx.moonhome.join("lib");
+    let mut archive = archive(&lib_path, &CliReporter).await?;
 
     // backup name, specified or automatically generated
-    let backup_name = std::iter::once(args.name.clone().unwrap_or_else(|| {
+    let backup_name = strip_zip_suffix(&args.name.clone().unwrap_or_else(|| {
         let datetime = chrono::Local::now();
         datetime.format("%Y%m%d-%H%M%S").to_string()
-    })).map(|s| {
-        s.strip_suffix(".zip").map(|rest| rest.to_string()).unwrap_or(s)
-    }).next().unwrap();
+    }));
     
     // write archive to disk
-    let write_path = core_backups_path().join(format!("{}.zip", backup_name));
-    println!("writing backup file {}", write_path.display());
-    let mut write_file = std::fs::File::create_new(write_path)?;
-    std::io::copy(&mut archive_reader, &mut write_file)?;
+    let write_path = core_backups_path(ctx).join(format!("{}.zip", backup_name));
+    if ctx.dry_run {
+        let plan = Plan { writes: vec![write_path, core_backups_path(ctx).join(format!("{}.json", backup_name))], ..Plan::default() };
+        return super::print_plan(ctx, &plan);
+    }
+    let metadata = new_metadata(ctx, &mut archive, args.description.clone())?;
+    info!("writing backup file {}", write_path.display());
+    save_backup(ctx, &backup_name, archive, &metadata)?;
 
-    println!("core backup complete. backup name: {}", &backup_name);
+    info!("core backup complete. backup name: {}", &backup_name);
 
     Ok(())
 }
 
-pub async fn restore(args: &crate::cmdline::CoreRestoreArgs) -> Result<()> {
-    println!("MoonBit homedir: {}", global().moonhome.display());
-    use_multimoon_home().await?;
+pub async fn restore(ctx: &Context, args: &crate::cmdline::CoreRestoreArgs) -> Result<()> {
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
+    use_multimoon_home(ctx).await?;
     
     // load zip archive from disk
-    let backup_name = std::iter::once(args.name.clone()).map(|s| {
-        s.strip_suffix(".zip").map(|rest| rest.to_string()).unwrap_or(s)
-    }).next().unwrap();
-    let read_path = core_backups_path().join(format!("{}.zip", &backup_name));
-    println!("reading backup file {}", read_path.display());
-    let archive_file = std::fs::File::open(&read_path)?;
-    let mut archive = zip::ZipArchive::new(archive_file)?;
+    let backup_name = strip_zip_suffix(&args.name);
+    let (read_path, mut archive) = open_backup(ctx, &backup_name)?;
 
-    let lib_path = global().moonhome.join("lib");
-    println!("extracting core to lib path {}", lib_path.display());
-    extract(lib_path, &mut archive).await?;
+    let lib_path = ctx.moonhome.join("lib");
+    let binary_path = ctx.moonhome.join("bin");
+    if ctx.dry_run {
+        let mut plan = plan_backup_before_overwrite(ctx).await?;
+        plan.extend(Plan {
+            removes: plan::existing([lib_path.join("core")]),
+            extracts: vec![plan::Extract { archive: read_path.display().to_string(), to: lib_path.clone(), files: Some(archive.len()) }],
+            commands: vec![crate::installer::plan_bundle_core(&binary_path, &lib_path)],
+            ..Plan::default()
+        });
+        return super::print_plan(ctx, &plan);
+    }
+    backup_before_overwrite(ctx).await?;
+    info!("replacing core in lib path {}", lib_path.display());
+    crate::core::replace(&lib_path, &mut archive, &CliReporter).await?;
+    crate::installer::bundle_core(&binary_path, &lib_path, &CliReporter)?;
 
-    println!("core restored from backup {}.", &backup_name);
+    info!("core restored from backup {}.", &backup_name);
 
     Ok(())
 }
 
-async fn use_multimoon_home() -> Result<()> {
-    let multimoonhome = global().multimoonhome.as_path();
-    println!("MultiMoon storage dir: {}", multimoonhome.display());
+/// Back up the core library in MoonBit home before overwriting it, unless it's unmodified (see [`core_change`]), and
+/// remove expired automatic backups. Without automatic backups, ask for confirmation instead.
+pub async fn backup_before_overwrite(ctx: &Context) -> Result<()> {
+    if ctx.dry_run {
+        return Ok(());
+    }
+    if ctx.auto_backups == 0 {
+        return confirm_core_overwrite(ctx, true, "overwrite");
+    }
+    let backup_name = crate::core::auto_backup_name(ctx);
+    match core_change(ctx, true)? {
+        CoreChange::Unmodified => return Ok(()),
+        CoreChange::Modified => info!("core library is modified, backing it up as {} before overwriting", &backup_name),
+        CoreChange::Unknown => info!("unable to tell if core library was modified (no record of the installed core), \
+            backing it up as {} before overwriting", &backup_name),
+    }
+
+    let mut archive = archive(ctx.moonhome.join("lib"), &CliReporter).await?;
+    let description = "automatic backup before overwriting".to_string();
+    let metadata = new_metadata(ctx, &mut archive, Some(description))?;
+    save_backup(ctx, &backup_name, archive, &metadata)?;
+    for path in crate::core::expired_auto_backups(ctx, ctx.auto_backups).await? {
+        info!("removing expired automatic backup {}", path.display());
+        std::fs::remove_file(&path)
+            .with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Plan of [`backup_before_overwrite`].
+pub async fn plan_backup_before_overwrite(ctx: &Context) -> Result<Plan> {
+    if ctx.auto_backups == 0 || core_change(ctx, true)? == CoreChange::Unmodified {
+        return Ok(Plan::default());
+    }
+    let backup_name = crate::core::auto_backup_name(ctx);
+    Ok(Plan {
+        writes: vec![
+            core_backups_path(ctx).join(format!("{}.zip", backup_name)),
+            core_backups_path(ctx).join(format!("{}.json", backup_name)),
+        ],
+        // the new backup is one of the kept ones
+        removes: crate::core::expired_auto_backups(ctx, ctx.auto_backups - 1).await?,
+        ..Plan::default()
+    })
+}
+
+/// Ask for confirmation before `action` (e.g. "overwrite") the core library in MoonBit home, if it was modified (see
+/// [`core_change`]).
+pub fn confirm_core_overwrite(ctx: &Context, with_backups: bool, action: &str) -> Result<()> {
+    if ctx.dry_run || ctx.yes {
+        return Ok(());
+    }
+    let lib_core_path = ctx.moonhome.join("lib").join("core");
+    match core_change(ctx, with_backups)? {
+        CoreChange::Unmodified => Ok(()),
+        CoreChange::Unknown => {
+            warn!("unable to tell if core library {} was modified (no record of the installed core).", lib_core_path.display());
+            Ok(())
+        },
+        CoreChange::Modified => {
+            warn!("core library {} differs from the installed core{}. (run `multimoon core backup` to keep it)",
+                lib_core_path.display(), if with_backups { " and from all backups" } else { "" });
+            super::confirm(ctx, action, &format!("modified core library {}", lib_core_path.display()))
+        },
+    }
+}
+
+/// Change of the core library in MoonBit home since it was installed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CoreChange {
+    /// Same as installed (or there is no core), or the same as a backup.
+    Unmodified,
+    Modified,
+    /// No record of the installed core, e.g. it was installed by an older version of MultiMoon.
+    Unknown,
+}
+
+/// Compare the core library in MoonBit home with the record of the installed core, and with all backups (if
+/// `with_backups`). Only local files are read.
+fn core_change(ctx: &Context, with_backups: bool) -> Result<CoreChange> {
+    let lib_core_path = ctx.moonhome.join("lib").join("core");
+    let Some(digest) = crate::core::digest(ctx.moonhome.join("lib"))? else {
+        return Ok(CoreChange::Unmodified);
+    };
+    let installed = crate::core::load_installed(ctx)?;
+    if installed.as_ref().is_some_and(|installed| installed.digest == digest) {
+        return Ok(CoreChange::Unmodified);
+    }
+    if with_backups {
+        if let Some(name) = find_backup(ctx, &digest)? {
+            debug!("core library {} is the same as backup {}", lib_core_path.display(), name);
+            return Ok(CoreChange::Unmodified);
+        }
+    }
+    match installed {
+        Some(_) => Ok(CoreChange::Modified),
+        None => Ok(CoreChange::Unknown),
+    }
+}
+
+/// Metadata of a new backup of the core library in MoonBit home, with the toolchain it was installed with.
+fn new_metadata(ctx: &Context, archive: &mut zip::ZipArchive<std::io::Cursor<Vec<u8>>>, description: Option<String>) -> Result<BackupMetadata> {
+    let mut metadata = archive_metadata(archive)?;
+    metadata.description = description;
+    metadata.git_commit = crate::core::git_commit(ctx.moonhome.join("lib"));
+    match crate::core::load_installed(ctx) {
+        Ok(Some(installed)) => {
+            metadata.toolchain = Some(installed.toolchain);
+            metadata.moonver = Some(installed.moonver);
+        },
+        Ok(None) => (),
+        Err(err) => warn!("unable to read record of the installed core, not recording toolchain in the backup: {:#}", err),
+    }
+    Ok(metadata)
+}
+
+/// Open the archive of backup `name`.
+fn open_backup(ctx: &Context, name: &str) -> Result<(PathBuf, zip::ZipArchive<std::fs::File>)> {
+    let read_path = core_backups_path(ctx).join(format!("{}.zip", name));
+    info!("reading backup file {}", read_path.display());
+    let archive_file = std::fs::File::open(&read_path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => Into::<anyhow::Error>::into(err)
+            .context(Error::NotFound(format!("error: core backup {} not found", name))),
+        _ => err.into(),
+    })?;
+    Ok((read_path, zip::ZipArchive::new(archive_file)?))
+}
+
+/// Backup name given on the command line, with an optional `.zip` suffix.
+fn strip_zip_suffix(name: &str) -> String {
+    name.strip_suffix(".zip").unwrap_or(name).to_string()
+}
+
+fn format_time(timestamp: i64) -> String {
+    use chrono::TimeZone;
+    chrono::Local.timestamp_opt(timestamp, 0).single()
+        .map(|datetime| datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "unknown time".to_string())
+}
+
+fn format_toolchain(metadata: &BackupMetadata) -> String {
+    match (&metadata.toolchain, &metadata.moonver) {
+        (Some(toolchain), Some(moonver)) => format!("{} [{}]", toolchain, moonver),
+        (Some(toolchain), None) => toolchain.clone(),
+        _ => "unknown toolchain".to_string(),
+    }
+}
+
+fn format_size(size: u64) -> String {
+    format!("{:.1} KiB", size as f64 / 1024.0)
+}
+
+async fn use_multimoon_home(ctx: &Context) -> Result<()> {
+    let multimoonhome = ctx.multimoonhome.as_path();
+    info!("MultiMoon storage dir: {}", multimoonhome.display());
+    if ctx.dry_run {
+        return Ok(());
+    }
     std::fs::create_dir_all(multimoonhome)?;
     let core_backups_path = multimoonhome.join("core-backups");
     std::fs::create_dir_all(&core_backups_path)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_context(home: &Path, auto_backups: usize) -> Context {
+        let mut ctx = Context::with_home(home.to_path_buf());
+        ctx.auto_backups = auto_backups;
+        std::fs::create_dir_all(ctx.moonhome.join("lib").join("core")).unwrap();
+        ctx
+    }
+
+    /// Modify the core library, so it differs from all backups.
+    fn modify_core(ctx: &Context, version: usize) {
+        let path = ctx.moonhome.join("lib").join("core").join("moon.mod.json");
+        std::fs::write(path, format!("{{\"version\": {}}}", version)).unwrap();
+    }
+
+    async fn auto_backups(ctx: &Context) -> Vec<String> {
+        crate::core::list(ctx).await.unwrap().into_iter()
+            .map(|backup| backup.name)
+            .filter(|name| name.starts_with(crate::core::AUTO_BACKUP_PREFIX))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn backups_in_quick_succession() {
+        let home = tempdir::TempDir::new("multimoon-home").unwrap();
+        let ctx = new_context(home.path(), 5);
+        for i in 0..3 {
+            modify_core(&ctx, i);
+            backup_before_overwrite(&ctx).await.unwrap();
+        }
+        assert_eq!(auto_backups(&ctx).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn keep_latest_auto_backups() {
+        let home = tempdir::TempDir::new("multimoon-home").unwrap();
+        let ctx = new_context(home.path(), 2);
+        let mut names = vec![];
+        for i in 0..4 {
+            modify_core(&ctx, i);
+            backup_before_overwrite(&ctx).await.unwrap();
+            let mut backups = auto_backups(&ctx).await;
+            backups.sort();
+            names.push(backups.pop().unwrap());
+        }
+        let mut kept = auto_backups(&ctx).await;
+        kept.sort();
+        assert_eq!(kept, names[2..]);
+        assert_eq!(std::fs::read_dir(core_backups_path(&ctx)).unwrap().count(), 4);
+    }
+
+    #[tokio::test]
+    async fn plan_keeps_latest_auto_backups() {
+        let home = tempdir::TempDir::new("multimoon-home").unwrap();
+        let ctx = new_context(home.path(), 2);
+        for i in 0..2 {
+            modify_core(&ctx, i);
+            backup_before_overwrite(&ctx).await.unwrap();
+        }
+        modify_core(&ctx, 2);
+        let mut oldest = auto_backups(&ctx).await;
+        oldest.sort();
+        let plan = plan_backup_before_overwrite(&ctx).await.unwrap();
+        assert_eq!(plan.writes.len(), 2);
+        assert_eq!(plan.removes, crate::core::backup_files(&ctx, &oldest[0]));
+    }
+}
@@ -0,0 +1,94 @@
+//! Subcommand env.
+
+use std::io::Write;
+
+use anyhow::Context as _;
+
+use crate::{cmdline::EnvShell, plan::Plan, shell::Shell, prelude::*};
+
+pub async fn env(ctx: &Context, args: &crate::cmdline::EnvArgs) -> Result<()> {
+    let moonhome = ctx.moonhome.to_str().context("unsupported path name")?;
+    let binary_path = ctx.moonhome.join("bin");
+    let binary_path = binary_path.to_str().context("unsupported path name")?;
+
+    if args.github {
+        return write_github_env(ctx, moonhome, binary_path);
+    }
+
+    let env_shell = match args.shell {
+        Some(env_shell) => env_shell,
+        None => detect_shell(),
+    };
+    let shell = match env_shell {
+        EnvShell::Sh => Shell::Sh,
+        EnvShell::Bash => Shell::Bash,
+        EnvShell::Zsh => Shell::Zsh,
+        EnvShell::Fish => Shell::Fish,
+        EnvShell::Nu => Shell::Nushell,
+        EnvShell::Powershell => Shell::PowerShell,
+        EnvShell::Tcsh => Shell::Tcsh,
+        EnvShell::Cmd => {
+            println!("set \"MOON_HOME={}\"", moonhome);
+            println!("set \"PATH={};%PATH%\"", binary_path);
+            return Ok(());
+        },
+        EnvShell::Json => {
+            let json = serde_json::json!({
+                "MOON_HOME": moonhome,
+                "PATH": [binary_path],
+            });
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            return Ok(());
+        },
+    };
+    println!("{}", shell.set_var("MOON_HOME", moonhome));
+    println!("{}", shell.prepend_path(binary_path));
+    Ok(())
+}
+
+fn detect_shell() -> EnvShell {
+    #[cfg(windows)]
+    {
+        EnvShell::Powershell
+    }
+
+    #[cfg(not(windows))]
+    {
+        match Shell::detect() {
+            Ok(Shell::Bash) => EnvShell::Bash,
+            Ok(Shell::Zsh) => EnvShell::Zsh,
+            Ok(Shell::Fish) => EnvShell::Fish,
+            Ok(Shell::Nushell) => EnvShell::Nu,
+            Ok(Shell::PowerShell) => EnvShell::Powershell,
+            Ok(Shell::Tcsh) => EnvShell::Tcsh,
+            Ok(Shell::Sh) | Err(_) => EnvShell::Sh,
+        }
+    }
+}
+
+/// Append to the files GitHub Actions reads environment of later steps from.
+fn write_github_env(ctx: &Context, moonhome: &str, binary_path: &str) -> Result<()> {
+    fn env_file(var: &str) -> Result<PathBuf> {
+        let path = std::env::var_os(var)
+            .with_context(|| format!("{} is not set (not running in GitHub Actions?)", var))?;
+        Ok(PathBuf::from(path))
+    }
+
+    fn append_line(var: &str, path: &Path, line: &str) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new().append(true).create(true).open(path)
+            .with_context(|| format!("cannot open {} file {}", var, path.display()))?;
+        writeln!(file, "{}", line)
+            .with_context(|| format!("cannot write {} file {}", var, path.display()))?;
+        info!("added `{}` to {}", line, var);
+        Ok(())
+    }
+
+    let (github_path, github_env) = (env_file("GITHUB_PATH")?, env_file("GITHUB_ENV")?);
+    if ctx.dry_run {
+        let plan = Plan { writes: vec![github_path, github_env], ..Plan::default() };
+        return super::print_plan(ctx, &plan);
+    }
+    append_line("GITHUB_PATH", &github_path, binary_path)?;
+    append_line("GITHUB_ENV", &github_env, &format!("MOON_HOME={}", moonhome))?;
+    Ok(())
+}
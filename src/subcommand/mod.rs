@@ -1,4 +1,46 @@
 //! Subcommands.
 
+pub mod completions;
+pub mod component;
 pub mod core;
+pub mod env;
+pub mod registry;
+pub mod self_install;
 pub mod toolchain;
+pub mod uninstall;
+pub mod update_self;
+
+use std::io::{IsTerminal, Write};
+
+use crate::{context::OutputFormat, error::Error, plan::Plan, prelude::*};
+
+/// Print the plan of `--dry-run`.
+pub fn print_plan(ctx: &Context, plan: &Plan) -> Result<()> {
+    if ctx.format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(plan)?);
+        return Ok(());
+    }
+    info!("(dry run, nothing will be changed)");
+    print!("{}", plan);
+    Ok(())
+}
+
+/// Ask the user to confirm `action` on `target`, unless `--yes`. Refused if stdin is not a terminal.
+pub fn confirm(ctx: &Context, action: &str, target: &str) -> Result<()> {
+    if ctx.yes {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::Cancelled(format!("error: refusing to {} {} without confirmation (add --yes to continue anyway)",
+            action, target)).into());
+    }
+
+    eprint!("{} {}? [y/N] ", action, target);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        return Ok(());
+    }
+    Err(Error::Cancelled("cancelled.".to_string()).into())
+}
@@ -0,0 +1,738 @@
+//! Subcommands under registry.
+
+use anyhow::Context as _;
+
+use serde::Serialize;
+
+use crate::{common::{sha256_checksum, write_file_atomic, xz_decompress}, error::Error, fetch, plan::{Download, Plan}, prelude::*};
+use crate::registry::{self, File, Registry, Toolchain, INDEX_FILENAME};
+
+pub async fn mirror(ctx: &Context, args: &crate::cmdline::RegistryMirrorArgs) -> Result<()> {
+    let archs: Vec<String> = if args.all_archs {
+        crate::global::ARCHS.iter().map(|s| s.to_string()).collect()
+    } else if args.archs.is_empty() {
+        vec![arch().to_string()]
+    } else {
+        args.archs.clone()
+    };
+
+    info!("mirroring registry {} to {}", ctx.registry, args.output.display());
+    if !ctx.dry_run {
+        std::fs::create_dir_all(&args.output)
+            .with_context(|| format!("mirror error: failed to create {}", args.output.display()))?;
+    }
+
+    let mut plan = Plan::default();
+    let client = reqwest::Client::new();
+    for arch in &archs {
+        // download source index, and load previously mirrored index (if any)
+        let source = registry::get_arch(&ctx.registry, arch).await?;
+        let source_downloadfrom = Url::parse(&source.downloadfrom)?;
+        let index_path = args.output.join(arch).join(INDEX_FILENAME);
+        let mut toolchains = match read_index(&index_path)? {
+            Some(existing) => existing.toolchains,
+            None => vec![],
+        };
+
+        for name in &args.toolchains {
+            if !source.toolchains.iter().any(|t| &t.name == name) {
+                warn!("toolchain {} not found in registry for {}", name, arch);
+            }
+        }
+
+        // mirror toolchains not mirrored yet
+        let mut mirrored_count = 0;
+        let selected = source.toolchains.iter()
+            .filter(|t| args.toolchains.is_empty() || args.toolchains.contains(&t.name));
+        for toolchain in selected {
+            let mirrored = toolchains.iter().find(|t| t.name == toolchain.name);
+            if mirrored.is_some_and(|t| is_complete(&args.output, arch, t, toolchain)) {
+                info!("toolchain {} [{}] for {} is already mirrored.", &toolchain.name, &toolchain.moonver, arch);
+                continue;
+            }
+
+            info!("mirroring toolchain {} [{}] for {} ...", &toolchain.name, &toolchain.moonver, arch);
+            let toolchain = mirror_toolchain(ctx, &client, &source_downloadfrom, &args.output, arch, toolchain, &mut plan).await?;
+            toolchains.retain(|t| t.name != toolchain.name);
+            toolchains.push(toolchain);
+            mirrored_count += 1;
+        }
+
+        // mirror MultiMoon release
+        let multimoon = match &source.multimoon {
+            Some(release) => {
+                info!("mirroring MultiMoon {} for {} ...", &release.version, arch);
+                let url_prefix = source_downloadfrom.join(&release.dir(arch))?;
+                let dir = args.output.join(release.dir(arch));
+                let bin = mirror_file(ctx, &client, &url_prefix, &dir, &release.bin, true, &mut plan).await?;
+                Some(registry::Release { bin, ..release.clone() })
+            },
+            None => None,
+        };
+
+        // write index after all files are in place, so the mirror is always consistent
+        toolchains.sort_by_key(|a| a.last_modified);
+        let index = Registry {
+            schema_version: source.schema_version,
+            toolchains,
+            last_modified: source.last_modified,
+            downloadfrom: args.downloadfrom.clone(),
+            multimoon,
+        };
+        if ctx.dry_run {
+            plan.writes.push(index_path);
+            continue;
+        }
+        info!("writing registry index {}", index_path.display());
+        std::fs::create_dir_all(args.output.join(arch))
+            .with_context(|| format!("mirror error: failed to create {}", args.output.join(arch).display()))?;
+        write_file_atomic(&index_path, &serde_json::to_vec_pretty(&index)?)?;
+
+        info!("mirrored {} new toolchains for {}. ({} toolchains in total)", mirrored_count, arch, index.toolchains.len());
+    }
+
+    if ctx.dry_run {
+        return super::print_plan(ctx, &plan);
+    }
+    info!("registry mirror complete.");
+    Ok(())
+}
+
+pub async fn build(ctx: &Context, args: &crate::cmdline::RegistryBuildArgs) -> Result<()> {
+    let root = args.path.as_path();
+    info!("building registry indexes in {}", root.display());
+    crate::installer::get_installer(&args.installer)?;
+    let install_spec = match &args.install_spec {
+        Some(path) => {
+            let content = std::fs::read(path)
+                .with_context(|| format!("error reading file {}", path.display()))?;
+            let spec = serde_json::from_slice::<registry::InstallSpec>(&content)
+                .with_context(|| Error::Registry(format!("build error: invalid installation steps {}", path.display())))?;
+            Some(spec)
+        },
+        None => None,
+    };
+
+    // collect toolchain directories (any directory other than the arch index directories)
+    let mut toolchain_names = vec![];
+    let readdir = std::fs::read_dir(root)
+        .with_context(|| format!("build error: failed to read directory {}", root.display()))?;
+    for entry in readdir {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().into_string()
+            .map_err(|osstr| anyhow!("unsupported file name {}", osstr.to_string_lossy()))?;
+        if !crate::global::ARCHS.contains(&name.as_str()) {
+            toolchain_names.push(name);
+        }
+    }
+    toolchain_names.sort();
+
+    let mut plan = Plan::default();
+    for arch in crate::global::ARCHS {
+        let mut toolchains = vec![];
+        for name in &toolchain_names {
+            if !root.join(name).join(arch).is_dir() {
+                continue;
+            }
+            info!("scanning toolchain {} for {} ...", name, arch);
+            let mut toolchain = build_toolchain(root, name, arch, &args.installer)?;
+            toolchain.install = install_spec.clone();
+            crate::installer::check_toolchain(&toolchain, arch)?;
+            toolchains.push(toolchain);
+        }
+        if toolchains.is_empty() {
+            continue;
+        }
+
+        // MultiMoon releases are published by hand, keep any in the existing index
+        let index_path = root.join(arch).join(INDEX_FILENAME);
+        let multimoon = read_index(&index_path)?.and_then(|existing| existing.multimoon);
+
+        toolchains.sort_by_key(|a| a.last_modified);
+        let index = Registry {
+            schema_version: registry::SCHEMA_VERSION,
+            last_modified: toolchains.iter().map(|t| t.last_modified).max().unwrap_or(0),
+            toolchains,
+            downloadfrom: args.downloadfrom.clone(),
+            multimoon,
+        };
+        if ctx.dry_run {
+            plan.writes.push(index_path);
+            continue;
+        }
+        info!("writing registry index {} ({} toolchains)", index_path.display(), index.toolchains.len());
+        std::fs::create_dir_all(root.join(arch))
+            .with_context(|| format!("build error: failed to create {}", root.join(arch).display()))?;
+        write_file_atomic(&index_path, &serde_json::to_vec_pretty(&index)?)?;
+    }
+
+    if ctx.dry_run {
+        return super::print_plan(ctx, &plan);
+    }
+    info!("registry build complete.");
+    Ok(())
+}
+
+/// A problem found by `registry validate`.
+#[derive(Serialize, Debug)]
+struct ValidationIssue {
+    check: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    toolchain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    message: String,
+}
+
+/// Report printed by `registry validate`.
+#[derive(Serialize, Debug)]
+struct ValidationReport {
+    index: String,
+    arch: String,
+    valid: bool,
+    toolchains: usize,
+    files_downloaded: usize,
+    errors: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    fn error(&mut self, check: &'static str, toolchain: Option<&Toolchain>, file: Option<&File>, message: String) {
+        error!("[{}] {}", check, &message);
+        self.errors.push(ValidationIssue {
+            check,
+            toolchain: toolchain.map(|t| t.name.clone()),
+            file: file.map(|f| f.filename.clone()),
+            message,
+        });
+    }
+}
+
+pub async fn validate(args: &crate::cmdline::RegistryValidateArgs) -> Result<()> {
+    // locate index, which can be a file, a directory containing the index file or an URL
+    let location = Path::new(&args.index);
+    let url = if location.is_file() {
+        let path = std::path::absolute(location)?;
+        Url::from_file_path(&path).map_err(|_| anyhow!("unsupported path {}", path.display()))?
+    } else if location.is_dir() {
+        fetch::parse_location(&args.index)?.join(INDEX_FILENAME)?
+    } else {
+        Url::parse(&args.index).with_context(|| format!("invalid registry index location {}", &args.index))?
+    };
+    let arch = args.arch.clone().unwrap_or_else(|| {
+        url.path_segments()
+            .and_then(|mut segments| segments.rfind(|s| !s.is_empty() && *s != INDEX_FILENAME))
+            .filter(|name| crate::global::ARCHS.contains(name))
+            .unwrap_or(arch())
+            .to_string()
+    });
+
+    // all progress goes to stderr (see `main`), only the report is printed to stdout
+    let mut report = ValidationReport {
+        index: url.to_string(),
+        arch: arch.clone(),
+        valid: false,
+        toolchains: 0,
+        files_downloaded: 0,
+        errors: vec![],
+    };
+    info!("validating registry index {} for {}", &url, &arch);
+    let client = reqwest::Client::new();
+    match fetch::fetch(&client, &url).await {
+        Ok(content) => match registry::parse(&content) {
+            Ok(registry) => validate_registry(&client, &url, &arch, &registry, args.download, &mut report).await,
+            Err(err) => report.error("schema", None, None, format!("{:#}", err)),
+        },
+        Err(err) => report.error("fetch", None, None, format!("{:#}", err)),
+    }
+    report.valid = report.errors.is_empty();
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if report.valid {
+        info!("registry index is valid.");
+        Ok(())
+    } else {
+        Err(Error::Registry(format!("registry validation failed with {} errors", report.errors.len())).into())
+    }
+}
+
+async fn validate_registry(client: &reqwest::Client, url: &Url, arch: &str, registry: &Registry, download: bool, report: &mut ValidationReport) {
+    report.toolchains = registry.toolchains.len();
+    if registry.toolchains.is_empty() {
+        report.error("toolchains", None, None, "registry has no toolchains".to_string());
+    }
+
+    let downloadfrom = match fetch::resolve_location(url, &registry.downloadfrom) {
+        Ok(downloadfrom) => Some(downloadfrom),
+        Err(err) => {
+            report.error("downloadfrom", None, None, format!("unresolvable registry downloadfrom {}: {:#}", &registry.downloadfrom, err));
+            None
+        },
+    };
+
+    if let Some(release) = &registry.multimoon {
+        if !is_valid_checksum(&release.bin.checksum) {
+            report.error("multimoon", None, Some(&release.bin), format!("MultiMoon {} has an invalid checksum {}", &release.version, &release.bin.checksum));
+        }
+    }
+
+    let mut names = std::collections::HashSet::new();
+    for toolchain in &registry.toolchains {
+        let t = Some(toolchain);
+        if !names.insert(toolchain.name.as_str()) {
+            report.error("unique-name", t, None, format!("duplicate toolchain name {}", &toolchain.name));
+        }
+        if let Some(min) = &toolchain.min_multimoon_version {
+            let well_formed = min.trim_start_matches('v').split(['-', '+']).next()
+                .is_some_and(|v| !v.is_empty() && v.split('.').all(|c| c.parse::<u64>().is_ok()));
+            if !well_formed {
+                report.error("min-multimoon-version", t, None, format!("invalid min_multimoon_version {}", min));
+            }
+        }
+        if let Err(err) = crate::installer::check_toolchain(toolchain, arch) {
+            // toolchains for newer clients may use installers unknown to this version
+            if toolchain.is_supported() {
+                report.error("installer", t, None, format!("{:#}", err));
+            }
+        }
+        if toolchain.bin.is_empty() {
+            report.error("bin", t, None, format!("toolchain {} has no binaries", &toolchain.name));
+        }
+        if toolchain.core.is_empty() {
+            report.error("core", t, None, format!("toolchain {} has no core", &toolchain.name));
+        }
+
+        for file in &toolchain.bin {
+            let undeclared = file.component.as_ref()
+                .filter(|name| !toolchain.components.iter().any(|c| &&c.name == name));
+            if let Some(name) = undeclared {
+                report.error("component", t, Some(file), format!("file {} belongs to undeclared component {}", &file.filename, name));
+            }
+        }
+
+        let files = toolchain.bin.iter().map(|f| (f, arch, true))
+            .chain(toolchain.core.iter().map(|f| (f, "multiarch", false)));
+        for (file, dir, xz) in files {
+            let f = Some(file);
+            if !is_valid_checksum(&file.checksum) {
+                report.error("checksum", t, f, format!("file {} has an invalid checksum {}", &file.filename, &file.checksum));
+            }
+
+            let Some(downloadfrom) = &downloadfrom else { continue };
+            let file_url = downloadfrom.join(&format!("{}/{}/", toolchain.name, dir))
+                .map_err(Into::<anyhow::Error>::into)
+                .and_then(|prefix| fetch::resolve_file(&prefix, &file.downloadfrom));
+            let file_url = match file_url {
+                Ok(file_url) => file_url,
+                Err(err) => {
+                    report.error("downloadfrom", t, f, format!("unresolvable downloadfrom {}: {:#}", &file.downloadfrom, err));
+                    continue;
+                },
+            };
+
+            if download {
+                info!("downloading {} ...", &file_url);
+                let content = match fetch::fetch(client, &file_url).await {
+                    Ok(content) => content,
+                    Err(err) => {
+                        report.error("download", t, f, format!("failed to download {}: {:#}", &file_url, err));
+                        continue;
+                    },
+                };
+                report.files_downloaded += 1;
+                let content = if xz {
+                    match xz_decompress(&content) {
+                        Ok(content) => content,
+                        Err(err) => {
+                            report.error("download", t, f, format!("error reading xz archive {}: {}", &file_url, err));
+                            continue;
+                        },
+                    }
+                } else {
+                    content
+                };
+                if sha256_checksum(&content) != file.checksum {
+                    report.error("checksum-match", t, f, format!("checksum check for {} failed!", &file.filename));
+                }
+            }
+        }
+    }
+}
+
+/// Check if `checksum` is in the format of `sha256:<64 lowercase hex digits>`.
+fn is_valid_checksum(checksum: &str) -> bool {
+    checksum.strip_prefix("sha256:")
+        .is_some_and(|hex| hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)))
+}
+
+/// Read a registry index from disk, `None` if it doesn't exist.
+fn read_index(path: &Path) -> Result<Option<Registry>> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Into::<anyhow::Error>::into(err)
+            .context(format!("error reading registry index {}", path.display()))),
+    };
+    let registry = registry::parse(&content)
+        .with_context(|| format!("registry error: failed to load registry index {}", path.display()))?;
+    Ok(Some(registry))
+}
+
+/// Check if all files of `source` toolchain are mirrored with matching checksums in the `mirrored` entry.
+fn is_complete(root: &Path, arch: &str, mirrored: &Toolchain, source: &Toolchain) -> bool {
+    let bin_path = root.join(&mirrored.name).join(arch);
+    let core_path = root.join(&mirrored.name).join("multiarch");
+    let is_mirrored = |files: &[File], mirrored_files: &[File], dir: &Path, xz: bool| {
+        files.len() == mirrored_files.len() && files.iter().all(|file| {
+            mirrored_files.iter()
+                .find(|f| f.filename == file.filename && f.checksum == file.checksum)
+                .is_some_and(|f| local_checksum(&dir.join(&f.downloadfrom), xz).as_ref() == Some(&file.checksum))
+        })
+    };
+    is_mirrored(&source.bin, &mirrored.bin, &bin_path, true)
+        && is_mirrored(&source.core, &mirrored.core, &core_path, false)
+}
+
+/// Checksum of a local file (of decompressed content if `xz`), `None` if it can't be read.
+fn local_checksum(path: &Path, xz: bool) -> Option<String> {
+    let content = std::fs::read(path).ok()?;
+    if xz {
+        Some(sha256_checksum(&xz_decompress(&content).ok()?))
+    } else {
+        Some(sha256_checksum(&content))
+    }
+}
+
+/// Build the index entry of toolchain `name` for `arch` from files in `root`.
+fn build_toolchain(root: &Path, name: &str, arch: &str, installer: &str) -> Result<Toolchain> {
+    let toolchain_path = root.join(name);
+    let mut last_modified = 0;
+
+    // binaries are xz compressed, checksums are of decompressed content
+    let mut bin = vec![];
+    let mut bin_contents = vec![];
+    for (file_name, path) in list_files(&toolchain_path.join(arch))? {
+        let Some(filename) = file_name.strip_suffix(".xz") else {
+            warn!("ignoring {} (binaries should be xz compressed)", path.display());
+            continue;
+        };
+        let compressed = std::fs::read(&path)
+            .with_context(|| format!("error reading file {}", path.display()))?;
+        let filecontent = xz_decompress(&compressed)
+            .with_context(|| format!("error reading xz archive {}", path.display()))?;
+        last_modified = last_modified.max(modified_timestamp(&path));
+        bin.push(File {
+            filename: filename.to_string(),
+            downloadfrom: file_name.clone(),
+            checksum: sha256_checksum(&filecontent),
+            component: None,
+        });
+        bin_contents.push((filename.to_string(), filecontent));
+    }
+    if bin.is_empty() {
+        return Err(anyhow!("build error: no binaries found for toolchain {} on {}", name, arch));
+    }
+
+    // core archives are shared by all platforms
+    let mut core = vec![];
+    let multiarch_path = toolchain_path.join("multiarch");
+    let core_files = if multiarch_path.is_dir() { list_files(&multiarch_path)? } else { vec![] };
+    for (file_name, path) in core_files {
+        if !file_name.ends_with(".zip") {
+            warn!("ignoring {} (core should be a zip archive)", path.display());
+            continue;
+        }
+        let content = std::fs::read(&path)
+            .with_context(|| format!("error reading file {}", path.display()))?;
+        last_modified = last_modified.max(modified_timestamp(&path));
+        core.push(File {
+            filename: file_name.clone(),
+            downloadfrom: file_name,
+            checksum: sha256_checksum(&content),
+            component: None,
+        });
+    }
+    if core.is_empty() {
+        return Err(anyhow!("build error: no core found for toolchain {} in {}", name, multiarch_path.display()));
+    }
+
+    let moonver = detect_moonver(&toolchain_path, name, arch, &bin_contents)?;
+
+    Ok(Toolchain {
+        name: name.to_string(),
+        moonver,
+        last_modified,
+        bin,
+        core,
+        installer: installer.to_string(),
+        min_multimoon_version: None,
+        install: None,
+        components: vec![],
+    })
+}
+
+/// Detect the MoonBit version of a toolchain, from a `moonver` file in the toolchain directory, or by running
+/// `moon version` if the toolchain is for current platform. Falls back to toolchain name.
+fn detect_moonver(toolchain_path: &Path, name: &str, arch: &str, bin_contents: &[(String, Vec<u8>)]) -> Result<String> {
+    let moonver_path = toolchain_path.join("moonver");
+    if moonver_path.is_file() {
+        let moonver = std::fs::read_to_string(&moonver_path)
+            .with_context(|| format!("error reading file {}", moonver_path.display()))?;
+        return Ok(moonver.trim().to_string());
+    }
+
+    if arch == crate::global::arch() {
+        let tempdir = tempdir::TempDir::new("multimoon-build")?;
+        for (filename, filecontent) in bin_contents {
+            let path = tempdir.path().join(filename);
+            std::fs::write(&path, filecontent)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            }
+        }
+        let output = std::process::Command::new(tempdir.path().join(crate::global::moon_executable_name()))
+            .arg("version")
+            .current_dir(tempdir.path())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                match parse_moon_version(&stdout) {
+                    Some(moonver) => return Ok(moonver),
+                    None => warn!("unrecognized output of `moon version` of toolchain {}: {}", name, stdout.trim()),
+                }
+            },
+            _ => warn!("failed to run `moon version` of toolchain {}", name),
+        }
+    }
+
+    warn!("unable to detect moonver of toolchain {} (add a `moonver` file to {}), using toolchain name",
+        name, toolchain_path.display());
+    Ok(name.to_string())
+}
+
+/// Version in the output of `moon version`, e.g. `0.1.20241031` in `moon 0.1.20241031 (7e5a6e6 2024-10-31)`.
+fn parse_moon_version(output: &str) -> Option<String> {
+    let line = output.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let version = line.split_whitespace().nth(1)?;
+    let looks_like_version = version.trim_start_matches('v').starts_with(|c: char| c.is_ascii_digit())
+        && version.contains('.');
+    looks_like_version.then(|| version.to_string())
+}
+
+/// List regular files in `dir` as (file name, path), sorted by file name.
+fn list_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut result = vec![];
+    let readdir = std::fs::read_dir(dir)
+        .with_context(|| format!("error reading directory {}", dir.display()))?;
+    for entry in readdir {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            let file_name = entry.file_name().into_string()
+                .map_err(|osstr| anyhow!("unsupported file name {}", osstr.to_string_lossy()))?;
+            result.push((file_name, entry.path()));
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+fn modified_timestamp(path: &Path) -> i64 {
+    std::fs::metadata(path).ok()
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|systime| systime.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+async fn mirror_toolchain(ctx: &Context, client: &reqwest::Client, downloadfrom: &Url, root: &Path, arch: &str, toolchain: &Toolchain, plan: &mut Plan) -> Result<Toolchain> {
+    let mut result = toolchain.clone();
+
+    let url_prefix = downloadfrom.join(&format!("{}/{}/", toolchain.name, arch))?;
+    let bin_path = root.join(&toolchain.name).join(arch);
+    result.bin = vec![];
+    for binary in &toolchain.bin {
+        result.bin.push(mirror_file(ctx, client, &url_prefix, &bin_path, binary, true, plan).await?);
+    }
+
+    let url_prefix = downloadfrom.join(&format!("{}/{}/", toolchain.name, "multiarch"))?;
+    let core_path = root.join(&toolchain.name).join("multiarch");
+    result.core = vec![];
+    for core in &toolchain.core {
+        result.core.push(mirror_file(ctx, client, &url_prefix, &core_path, core, false, plan).await?);
+    }
+
+    Ok(result)
+}
+
+/// Download a file to `dir` and verify its checksum (of decompressed content if `xz`), only adding it to `plan` in a
+/// dry run. Returns the file entry for the mirrored index.
+async fn mirror_file(ctx: &Context, client: &reqwest::Client, url_prefix: &Url, dir: &Path, file: &File, xz: bool, plan: &mut Plan) -> Result<File> {
+    let url = fetch::resolve_file(url_prefix, &file.downloadfrom)?;
+    let local_name = url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .with_context(|| format!("registry error: invalid download location {}", url))?
+        .to_string();
+    let local_path = dir.join(&local_name);
+
+    let verify = |content: &[u8]| -> Result<()> {
+        let hash = if xz {
+            let filecontent = xz_decompress(content).with_context(|| {
+                Error::Extract(format!("error reading xz archive {}", &url))
+            })?;
+            sha256_checksum(&filecontent)
+        } else {
+            sha256_checksum(content)
+        };
+        if hash != file.checksum {
+            return Err(Error::Checksum(format!("checksum check for {} failed!", &file.filename)).into());
+        }
+        Ok(())
+    };
+
+    let existing = std::fs::read(&local_path).ok();
+    if existing.is_some_and(|content| verify(&content).is_ok()) {
+        info!("using already downloaded {}", local_path.display());
+    } else if ctx.dry_run {
+        plan.downloads.push(Download { url: url.to_string(), size: fetch::content_length(client, &url).await });
+        plan.writes.push(local_path);
+    } else {
+        info!("downloading {} ...", &url);
+        let content = fetch::fetch(client, &url).await?;
+        verify(&content)?;
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("mirror error: failed to create {}", dir.display()))?;
+        write_file_atomic(&local_path, &content)?;
+    }
+
+    Ok(File {
+        filename: file.filename.clone(),
+        downloadfrom: local_name,
+        checksum: file.checksum.clone(),
+        component: file.component.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moon_version_is_second_field() {
+        assert_eq!(parse_moon_version("moon 0.1.20241031 (7e5a6e6 2024-10-31)\n").as_deref(), Some("0.1.20241031"));
+        assert_eq!(parse_moon_version("\n  moon v0.2.0\nmoonc 0.2.0\n").as_deref(), Some("v0.2.0"));
+    }
+
+    #[test]
+    fn moon_version_rejects_unrecognized_output() {
+        assert_eq!(parse_moon_version(""), None);
+        assert_eq!(parse_moon_version("moon"), None);
+        assert_eq!(parse_moon_version("moon version unknown"), None);
+        assert_eq!(parse_moon_version("moon 2024"), None);
+    }
+
+    fn xz_compress(data: &[u8]) -> Vec<u8> {
+        use std::io::Read;
+        let mut compressed = vec![];
+        xz2::read::XzEncoder::new(data, 6).read_to_end(&mut compressed).unwrap();
+        compressed
+    }
+
+    /// Write a registry with toolchain `t1` for current platform to `root`, with given binary and core contents.
+    fn write_source(root: &Path, binary: &[u8], core: &[u8]) {
+        let bin_path = root.join("t1").join(arch());
+        let core_path = root.join("t1").join("multiarch");
+        std::fs::create_dir_all(&bin_path).unwrap();
+        std::fs::create_dir_all(&core_path).unwrap();
+        std::fs::create_dir_all(root.join(arch())).unwrap();
+        std::fs::write(bin_path.join("moon.xz"), xz_compress(binary)).unwrap();
+        std::fs::write(core_path.join("core.zip"), core).unwrap();
+        let index = serde_json::json!({
+            "toolchains": [{
+                "name": "t1", "moonver": "0.1.0", "last_modified": 1, "installer": "initial",
+                "bin": [{ "filename": "moon", "downloadfrom": "moon.xz", "checksum": sha256_checksum(binary) }],
+                "core": [{ "filename": "core.zip", "downloadfrom": "core.zip", "checksum": sha256_checksum(core) }],
+            }],
+            "last_modified": 1,
+            "downloadfrom": "../",
+        });
+        std::fs::write(root.join(arch()).join(INDEX_FILENAME), serde_json::to_vec(&index).unwrap()).unwrap();
+    }
+
+    async fn run_mirror(source: &Path, output: &Path) -> Registry {
+        let mut ctx = Context::with_home(output.join("home"));
+        ctx.registry = fetch::parse_location(source.to_str().unwrap()).unwrap();
+        let args = crate::cmdline::RegistryMirrorArgs {
+            output: output.to_path_buf(),
+            archs: vec![],
+            all_archs: false,
+            toolchains: vec![],
+            downloadfrom: "../".to_string(),
+        };
+        mirror(&ctx, &args).await.unwrap();
+        read_index(&output.join(arch()).join(INDEX_FILENAME)).unwrap().unwrap()
+    }
+
+    fn mirrored_binary(output: &Path) -> Vec<u8> {
+        xz_decompress(&std::fs::read(output.join("t1").join(arch()).join("moon.xz")).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn mirror_toolchain_files() {
+        let (source, output) = (tempdir::TempDir::new("multimoon-source").unwrap(), tempdir::TempDir::new("multimoon-mirror").unwrap());
+        write_source(source.path(), b"moon v1", b"core v1");
+
+        let index = run_mirror(source.path(), output.path()).await;
+        assert_eq!(index.downloadfrom, "../");
+        assert_eq!(index.toolchains.len(), 1);
+        assert_eq!(index.toolchains[0].bin[0].checksum, sha256_checksum(b"moon v1"));
+        assert_eq!(mirrored_binary(output.path()), b"moon v1");
+        assert_eq!(std::fs::read(output.path().join("t1").join("multiarch").join("core.zip")).unwrap(), b"core v1");
+
+        // the mirror is a registry itself
+        let mirrored = registry::get_arch(&fetch::parse_location(output.path().to_str().unwrap()).unwrap(), arch()).await.unwrap();
+        assert_eq!(mirrored.toolchains[0].name, "t1");
+        assert!(is_complete(output.path(), arch(), &mirrored.toolchains[0], &index.toolchains[0]));
+    }
+
+    #[tokio::test]
+    async fn mirror_updates_changed_files() {
+        let (source, output) = (tempdir::TempDir::new("multimoon-source").unwrap(), tempdir::TempDir::new("multimoon-mirror").unwrap());
+        write_source(source.path(), b"moon v1", b"core v1");
+        run_mirror(source.path(), output.path()).await;
+
+        // upstream replaces the binary of the same toolchain
+        write_source(source.path(), b"moon v2", b"core v1");
+        let index = run_mirror(source.path(), output.path()).await;
+        assert_eq!(index.toolchains[0].bin[0].checksum, sha256_checksum(b"moon v2"));
+        assert_eq!(mirrored_binary(output.path()), b"moon v2");
+    }
+
+    #[tokio::test]
+    async fn mirror_repairs_corrupted_files() {
+        let (source, output) = (tempdir::TempDir::new("multimoon-source").unwrap(), tempdir::TempDir::new("multimoon-mirror").unwrap());
+        write_source(source.path(), b"moon v1", b"core v1");
+        run_mirror(source.path(), output.path()).await;
+
+        let core_path = output.path().join("t1").join("multiarch").join("core.zip");
+        std::fs::write(&core_path, b"corrupted").unwrap();
+        run_mirror(source.path(), output.path()).await;
+        assert_eq!(std::fs::read(&core_path).unwrap(), b"core v1");
+    }
+
+    #[test]
+    fn incomplete_without_files() {
+        let output = tempdir::TempDir::new("multimoon-mirror").unwrap();
+        let toolchain = serde_json::from_value::<Toolchain>(serde_json::json!({
+            "name": "t1", "moonver": "0.1.0", "last_modified": 1, "installer": "initial",
+            "bin": [{ "filename": "moon", "downloadfrom": "moon.xz", "checksum": sha256_checksum(b"moon") }],
+            "core": [],
+        })).unwrap();
+        assert!(!is_complete(output.path(), arch(), &toolchain, &toolchain));
+    }
+}
@@ -0,0 +1,69 @@
+//! Subcommands under self.
+
+use anyhow::Context as _;
+
+use crate::{plan::{self, Plan}, subcommand::uninstall::{remove_dir_if_empty, remove_from_path, remove_path, with_emptied_dirs}, prelude::*};
+
+/// Directory where `self install` puts the MultiMoon executable.
+pub fn bin_path(ctx: &Context) -> PathBuf {
+    ctx.multimoonhome.join("bin")
+}
+
+fn executable_path(ctx: &Context) -> PathBuf {
+    bin_path(ctx).join(format!("multimoon{}", std::env::consts::EXE_SUFFIX))
+}
+
+pub async fn install(ctx: &Context, args: &crate::cmdline::SelfInstallArgs) -> Result<()> {
+    info!("MultiMoon storage dir: {}", ctx.multimoonhome.display());
+    let current = std::env::current_exe()
+        .and_then(|path| path.canonicalize())
+        .context("cannot locate MultiMoon executable")?;
+    let target = executable_path(ctx);
+    if ctx.dry_run {
+        let plan = Plan {
+            writes: if target.canonicalize().is_ok_and(|path| path == current) { vec![] } else { vec![target] },
+            shell_configs: crate::installer::plan_configure_path(ctx, &bin_path(ctx)),
+            ..Plan::default()
+        };
+        super::print_plan(ctx, &plan)?;
+        if args.with_toolchain {
+            crate::subcommand::toolchain::update_to_latest(ctx).await?;
+        }
+        return Ok(());
+    }
+
+    if target.canonicalize().is_ok_and(|path| path == current) {
+        info!("MultiMoon is already installed to {}.", target.display());
+    } else {
+        let content = std::fs::read(&current)
+            .with_context(|| format!("error reading file {}", current.display()))?;
+        std::fs::create_dir_all(bin_path(ctx))
+            .with_context(|| format!("install error: failed to create {}", bin_path(ctx).display()))?;
+        info!("installing {} to {} ...", current.display(), target.display());
+        crate::common::replace_executable(&target, &content)?;
+    }
+    crate::installer::configure_path(ctx, &bin_path(ctx), &crate::report::CliReporter);
+    info!("MultiMoon {} installed. (restart your shell to update PATH)", crate::registry::MULTIMOON_VERSION);
+
+    if args.with_toolchain {
+        crate::subcommand::toolchain::update_to_latest(ctx).await?;
+    }
+    Ok(())
+}
+
+pub async fn uninstall(ctx: &Context) -> Result<()> {
+    info!("MultiMoon storage dir: {}", ctx.multimoonhome.display());
+    if ctx.dry_run {
+        let plan = Plan {
+            removes: with_emptied_dirs(plan::existing([executable_path(ctx)]), &[&bin_path(ctx)]),
+            shell_configs: crate::shell::remove_path_from_shell(ctx, bin_path(ctx), true)?,
+            ..Plan::default()
+        };
+        return super::print_plan(ctx, &plan);
+    }
+    remove_from_path(ctx, &bin_path(ctx))?;
+    remove_path(&executable_path(ctx))?;
+    remove_dir_if_empty(&bin_path(ctx))?;
+    info!("MultiMoon uninstalled. (MoonBit toolchain is kept, run `multimoon uninstall` to remove everything)");
+    Ok(())
+}
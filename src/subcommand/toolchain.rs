@@ -1,41 +1,70 @@
 //! Subcommands under toolchain.
 
-use crate::{installer, registry, prelude::*};
+use serde::Serialize;
+
+use crate::{context::OutputFormat, error::Error, installer, registry::{self, Toolchain}, prelude::*};
+
+/// A toolchain in JSON output.
+#[derive(Serialize)]
+struct ToolchainInfo<'a> {
+    name: &'a str,
+    moonver: &'a str,
+    last_modified: i64,
+    current: bool,
+    supported: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_multimoon_version: Option<&'a str>,
+}
 
-pub async fn show() -> Result<()> {
-    use installer::Installer;
-    println!("MoonBit homedir: {}", global().moonhome.display());
+impl<'a> ToolchainInfo<'a> {
+    fn new(toolchain: &'a Toolchain, current: bool) -> Self {
+        ToolchainInfo {
+            name: &toolchain.name,
+            moonver: &toolchain.moonver,
+            last_modified: toolchain.last_modified,
+            current,
+            supported: toolchain.is_supported(),
+            min_multimoon_version: toolchain.min_multimoon_version.as_deref(),
+        }
+    }
+}
+
+pub async fn show(ctx: &Context) -> Result<()> {
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
 
     // download registry index
-    let registry = registry::get().await?;
+    let registry = registry::get(ctx).await?;
     if !(registry.toolchains.len() > 0) {
-        return Err(anyhow!("registry error: no toolchains found"));
+        return Err(Error::Registry("registry error: no toolchains found".to_string()).into());
     }
 
-    // iterate all toolchains, check if any
-    let mut toolchains = registry.toolchains.clone();
-    toolchains.sort_by(|a, b| b.last_modified.cmp(&a.last_modified));
-
-    for toolchain in &toolchains {
-        let installer = installer::get_installer(&toolchain.installer)?;
-        if installer.matches(toolchain).await? {
-            println!("using {} toolchain.", &toolchain.name);
-            return Ok(())
-        }
+    // check if any toolchain matches, latest first
+    let current = installer::current_toolchain(ctx, &registry).await?;
+    if ctx.format == OutputFormat::Json {
+        let json = serde_json::json!({
+            "moonhome": &ctx.moonhome,
+            "toolchain": current.as_ref().map(|t| ToolchainInfo::new(t, true)),
+        });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+    if let Some(toolchain) = current {
+        println!("using {} toolchain.", &toolchain.name);
+        return Ok(())
     }
 
     println!("using a toolchain not listed in the registry. (run `moon version` to see version)");
     return Ok(());
 }
 
-pub async fn list() -> Result<()> {
+pub async fn list(ctx: &Context) -> Result<()> {
     use installer::Installer;
-    println!("MoonBit homedir: {}", global().moonhome.display());
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
 
     // download registry index
-    let registry = registry::get().await?;
+    let registry = registry::get(ctx).await?;
     if !(registry.toolchains.len() > 0) {
-        return Err(anyhow!("registry error: no toolchains found"));
+        return Err(Error::Registry("registry error: no toolchains found".to_string()).into());
     }
 
     // print all toolchains
@@ -43,27 +72,43 @@ pub async fn list() -> Result<()> {
     toolchains.sort_by(|a, b| a.last_modified.cmp(&b.last_modified));
 
     let mut print = vec![];
+    let mut infos = vec![];
     for toolchain in &toolchains {
+        if !toolchain.is_supported() {
+            print.push(format!("{} [{}] (requires MultiMoon {} or newer)", &toolchain.name, &toolchain.moonver,
+                toolchain.min_multimoon_version.as_deref().unwrap_or_default()));
+            infos.push(ToolchainInfo::new(toolchain, false));
+            continue;
+        }
         let installer = installer::get_installer(&toolchain.installer)?;
-        let matches = installer.matches(toolchain).await?;
+        let matches = installer.matches(ctx, toolchain).await?;
         
         print.push(format!("{}{} [{}]", &toolchain.name, if matches { " (current)" } else { "" }, &toolchain.moonver));
+        infos.push(ToolchainInfo::new(toolchain, matches));
     }
 
+    if ctx.format == OutputFormat::Json {
+        let json = serde_json::json!({
+            "moonhome": &ctx.moonhome,
+            "toolchains": infos,
+        });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
     for print_line in print {
         println!("{}", print_line);
     }
     Ok(())
 }
 
-pub async fn update_to_latest() -> Result<()> {
+pub async fn update_to_latest(ctx: &Context) -> Result<()> {
     use installer::Installer;
-    println!("MoonBit homedir: {}", global().moonhome.display());
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
 
     // download registry index
-    let registry = registry::get().await?;
+    let registry = registry::get(ctx).await?;
     if !(registry.toolchains.len() > 0) {
-        return Err(anyhow!("registry error: no toolchains found"));
+        return Err(Error::Registry("registry error: no toolchains found".to_string()).into());
     }
 
     // find latest toolchain
@@ -72,44 +117,74 @@ pub async fn update_to_latest() -> Result<()> {
     let latest_toolchain = &toolchains[0];
 
     // check if latest, install if not
+    latest_toolchain.check_supported()?;
+    installer::check_toolchain(latest_toolchain, arch())?;
     let latest_installer = installer::get_installer(&latest_toolchain.installer)?;
-    let matches = latest_installer.matches(latest_toolchain).await?;
+    let matches = latest_installer.matches(ctx, latest_toolchain).await?;
     if matches {
-        println!("current installed toolchain is already latest version ({})", &latest_toolchain.name);
+        info!("current installed toolchain is already latest version ({})", &latest_toolchain.name);
         return Ok(());
     } else {
-        println!("updating toolchain to latest version {} [{}]", &latest_toolchain.name, &latest_toolchain.moonver);
-        latest_installer.install(&registry, latest_toolchain).await?;
+        info!("updating toolchain to latest version {} [{}]", &latest_toolchain.name, &latest_toolchain.moonver);
+        if ctx.dry_run {
+            let mut plan = super::core::plan_backup_before_overwrite(ctx).await?;
+            plan.extend(latest_installer.plan(ctx, &registry, latest_toolchain).await?);
+            return super::print_plan(ctx, &plan);
+        }
+        super::core::backup_before_overwrite(ctx).await?;
+        latest_installer.install(ctx, &registry, latest_toolchain, &crate::report::cli()).await?;
         return Ok(());
     }
 }
 
-pub async fn update(args: &crate::cmdline::ToolchainUpdateArgs) -> Result<()> {
+pub async fn update(ctx: &Context, args: &crate::cmdline::ToolchainUpdateArgs) -> Result<()> {
     use installer::Installer;
-    println!("MoonBit homedir: {}", global().moonhome.display());
+    info!("MoonBit homedir: {}", ctx.moonhome.display());
 
     // download registry index
-    let registry = registry::get().await?;
+    let registry = registry::get(ctx).await?;
     if !(registry.toolchains.len() > 0) {
-        return Err(anyhow!("registry error: no toolchains found"));
+        return Err(Error::Registry("registry error: no toolchains found".to_string()).into());
     }
 
     // find latest toolchain
-    let toolchain = match registry.toolchains.iter().filter(|&t| &t.name == &args.toolchain).next() {
+    let toolchain = match registry.toolchains.iter().find(|&t| t.name == args.toolchain) {
         Some(t) => t,
-        None => return Err(anyhow!("error: toolchain {} not found in registry", &args.toolchain)),
+        None => return Err(Error::NotFound(format!("error: toolchain {} not found in registry", &args.toolchain)).into()),
     };
 
     // check if latest, install if not
+    toolchain.check_supported()?;
+    installer::check_toolchain(toolchain, arch())?;
     let installer = installer::get_installer(&toolchain.installer)?;
-    let matches = installer.matches(toolchain).await?;
+    let matches = installer.matches(ctx, toolchain).await?;
     if matches && (!args.force) {
-        println!("current installed toolchain is already {}. (add --force to reinstall)", &toolchain.name);
+        info!("current installed toolchain is already {}. (add --force to reinstall)", &toolchain.name);
         return Ok(());
     } else {
-        println!("installing toolchain {} [{}]", &toolchain.name, &toolchain.moonver);
-        installer.install(&registry, toolchain).await?;
+        info!("installing toolchain {} [{}]", &toolchain.name, &toolchain.moonver);
+        if ctx.dry_run {
+            let mut plan = super::core::plan_backup_before_overwrite(ctx).await?;
+            plan.extend(installer.plan(ctx, &registry, toolchain).await?);
+            return super::print_plan(ctx, &plan);
+        }
+        super::core::backup_before_overwrite(ctx).await?;
+        installer.install(ctx, &registry, toolchain, &crate::report::cli()).await?;
         return Ok(());
     }
 }
 
+
+pub async fn installers() -> Result<()> {
+    use installer::Installer;
+    for installer in installer::installers() {
+        if installer.aliases().is_empty() {
+            println!("{}", installer.name());
+        } else {
+            println!("{} (aliases: {})", installer.name(), installer.aliases().join(", "));
+        }
+        println!("  {}", installer.description());
+        println!("  platforms: {}", installer.platforms().join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Subcommand uninstall.
+
+use anyhow::Context as _;
+
+use crate::{plan::{self, Plan}, prelude::*};
+
+pub async fn uninstall(ctx: &Context, args: &crate::cmdline::UninstallArgs) -> Result<()> {
+    let moonhome = ctx.moonhome.as_path();
+    let multimoonhome = ctx.multimoonhome.as_path();
+    info!("MoonBit homedir: {}", moonhome.display());
+    info!("MultiMoon storage dir: {}", multimoonhome.display());
+    super::core::confirm_core_overwrite(ctx, args.keep_backups, "delete")?;
+
+    // PATH configured by installers and `self install`
+    let path_dirs = [moonhome.join("bin"), crate::subcommand::self_install::bin_path(ctx)];
+    let removes = removed_paths(ctx, args.keep_backups)?;
+    if ctx.dry_run {
+        let mut plan = Plan::default();
+        for path in &path_dirs {
+            plan.shell_configs.extend(crate::shell::remove_path_from_shell(ctx, path, true)?);
+        }
+        plan.removes = with_emptied_dirs(removes, &[moonhome, multimoonhome]);
+        return super::print_plan(ctx, &plan);
+    }
+
+    for path in &path_dirs {
+        remove_from_path(ctx, path)?;
+    }
+    for path in &removes {
+        remove_path(path)?;
+    }
+    remove_dir_if_empty(moonhome)?;
+    remove_dir_if_empty(multimoonhome)?;
+    info!("MoonBit and MultiMoon data uninstalled. (restart your shell to update PATH)");
+    Ok(())
+}
+
+/// Existing files managed by MultiMoon in MoonBit home, and data of MultiMoon (except core backups if
+/// `keep_backups`).
+fn removed_paths(ctx: &Context, keep_backups: bool) -> Result<Vec<PathBuf>> {
+    let moonhome = ctx.moonhome.as_path();
+    let multimoonhome = ctx.multimoonhome.as_path();
+    let mut paths = plan::existing([
+        moonhome.join("bin"),
+        moonhome.join("lib"),
+        crate::component::state_path(ctx),
+        crate::core::installed_path(ctx),
+    ]);
+
+    let core_backups_path = crate::core::core_backups_path(ctx);
+    match std::fs::read_dir(multimoonhome) {
+        Ok(readdir) => {
+            for entry in readdir {
+                let path = entry?.path();
+                if keep_backups && path == core_backups_path {
+                    info!("keeping core backups in {}", path.display());
+                    continue;
+                }
+                paths.push(path);
+            }
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => return Err(Into::<anyhow::Error>::into(err)
+            .context(format!("error reading directory {}", multimoonhome.display()))),
+    }
+    Ok(paths)
+}
+
+/// `removes` and those of `dirs` which would be empty after removing them, as [`remove_dir_if_empty`] does.
+pub fn with_emptied_dirs(mut removes: Vec<PathBuf>, dirs: &[&Path]) -> Vec<PathBuf> {
+    for dir in dirs {
+        let Ok(readdir) = std::fs::read_dir(dir) else { continue };
+        let mut entries = readdir.map(|entry| entry.map(|entry| entry.path()));
+        if entries.all(|path| path.is_ok_and(|path| removes.contains(&path))) {
+            removes.push(dir.to_path_buf());
+        }
+    }
+    removes
+}
+
+/// Remove `path` from PATH configured in shell config files (or user environment variables on Windows).
+pub fn remove_from_path(ctx: &Context, path: &Path) -> Result<()> {
+    let changed = crate::shell::remove_path_from_shell(ctx, path, false)?;
+    for location in &changed {
+        info!("removing {} from PATH in {}", path.display(), location);
+    }
+    Ok(())
+}
+
+/// Remove a file or directory if it exists.
+pub fn remove_path(path: &Path) -> Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    info!("removing {}", path.display());
+    if metadata.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }.with_context(|| format!("failed to remove {}", path.display()))
+}
+
+pub fn remove_dir_if_empty(path: &Path) -> Result<()> {
+    let is_empty = match std::fs::read_dir(path) {
+        Ok(mut readdir) => readdir.next().is_none(),
+        Err(_) => return Ok(()),
+    };
+    if is_empty {
+        info!("removing {}", path.display());
+        std::fs::remove_dir(path)
+            .with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Subcommand update-self.
+
+use anyhow::Context as _;
+
+use crate::{common::{compare_versions, replace_executable, sha256_checksum, xz_decompress}, error::Error, plan::{Download, Plan}, registry::{self, MULTIMOON_VERSION}, prelude::*};
+
+/// Update the running executable to the latest release in the registry, verified by checksum only.
+pub async fn update_self(ctx: &Context, args: &crate::cmdline::UpdateSelfArgs) -> Result<()> {
+    let registry = registry::get(ctx).await?;
+    let release = registry.multimoon.as_ref()
+        .with_context(|| format!("registry {} doesn't provide MultiMoon releases for {}", ctx.registry, arch()))?;
+
+    if compare_versions(&release.version, MULTIMOON_VERSION).is_le() {
+        println!("MultiMoon {} is up to date.", MULTIMOON_VERSION);
+        return Ok(());
+    }
+    if args.check {
+        println!("MultiMoon {} is available. (current version: {})", &release.version, MULTIMOON_VERSION);
+        return Ok(());
+    }
+
+    // download the new executable, checking its checksum in the registry (against corrupted downloads, not tampering:
+    // the checksum comes from the same registry, and there is no signature)
+    let client = reqwest::Client::new();
+    let url_prefix = Url::parse(&registry.downloadfrom)?.join(&release.dir(arch()))?;
+    let url = crate::fetch::resolve_file(&url_prefix, &release.bin.downloadfrom)?;
+    let executable = std::env::current_exe()
+        .and_then(|path| path.canonicalize())
+        .context("cannot locate MultiMoon executable")?;
+    if ctx.dry_run {
+        let plan = Plan {
+            resolved: Some(format!("MultiMoon {}", &release.version)),
+            downloads: vec![Download { url: url.to_string(), size: crate::fetch::content_length(&client, &url).await }],
+            writes: vec![executable],
+            ..Plan::default()
+        };
+        return super::print_plan(ctx, &plan);
+    }
+    info!("downloading MultiMoon {} from {} ...", &release.version, &url);
+    let compressed = crate::fetch::fetch(&client, &url).await?;
+    let content = xz_decompress(&compressed).with_context(|| {
+        Error::Extract(format!("error reading xz archive {}", &url))
+    })?;
+    if sha256_checksum(&content) != release.bin.checksum {
+        return Err(Error::Checksum(format!("checksum check for {} failed!", &release.bin.filename)).into());
+    }
+
+    info!("replacing {} ...", executable.display());
+    replace_executable(&executable, &content)?;
+
+    info!("successfully updated MultiMoon from {} to {}.", MULTIMOON_VERSION, &release.version);
+    Ok(())
+}